@@ -140,6 +140,81 @@ pub fn is_network_error(input: TokenStream) -> TokenStream {
     })
 }
 
+/// Implements the `ToExitCode` trait for an enum.
+///
+/// By default, a unit variant exits with status code 1, and a variant with a single unnamed field forwards that field's implementation. Other variants have no default and will generate a compile error.
+///
+/// The behavior for any variant can be customized to return a constant value by setting e.g. `#[exit_code = 64]`. Other expressions, such as constants from `wheel::sysexits`, can be specified as e.g. `#[exit_code(wheel::sysexits::USAGE)]`.
+#[proc_macro_derive(ToExitCode, attributes(exit_code))]
+pub fn to_exit_code(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ty = input.ident;
+    let arms = match input.data {
+        Data::Enum(DataEnum { variants, .. }) => {
+            let mut arms = Vec::with_capacity(variants.len());
+            for variant in variants {
+                let variant_name = &variant.ident;
+                arms.push(if let Some(attr) = variant.attrs.iter().find(|attr| attr.path().get_ident().is_some_and(|ident| ident == "exit_code")) {
+                    let value = match &attr.meta {
+                        Meta::NameValue(name_value) => Ok(name_value.value.clone()),
+                        Meta::List(list) => list.parse_args::<Expr>(),
+                        Meta::Path(_) => Err(Error::new(attr.span(), "expected `#[exit_code = ...]` or `#[exit_code(...)]`")),
+                    };
+                    let value = match value {
+                        Ok(value) => value,
+                        Err(e) => {
+                            let e = e.into_compile_error();
+                            return quote! {
+                                impl ::wheel::traits::ToExitCode for #ty {
+                                    fn to_exit_code(&self) -> i32 { 1 }
+                                }
+
+                                #e
+                            }.into()
+                        }
+                    };
+                    quote_spanned! {attr.span()=>
+                        #ty::#variant_name { .. } => #value,
+                    }
+                } else {
+                    match &variant.fields {
+                        Fields::Unit => quote_spanned! {variant.span()=>
+                            #ty::#variant_name => 1,
+                        },
+                        Fields::Unnamed(FieldsUnnamed { unnamed, .. }) if unnamed.len() == 1 => quote_spanned! {variant.span()=>
+                            #ty::#variant_name(e) => ::wheel::traits::ToExitCode::to_exit_code(e),
+                        },
+                        Fields::Unnamed(_) | Fields::Named(_) => return quote_spanned! {variant.span()=>
+                            impl ::wheel::traits::ToExitCode for #ty {
+                                fn to_exit_code(&self) -> i32 { 1 }
+                            }
+
+                            compile_error!("#[exit_code] is required for variants with named fields or multiple fields");
+                        }.into(),
+                    }
+                });
+            }
+            arms
+        }
+        _ => return quote! {
+            impl ::wheel::traits::ToExitCode for #ty {
+                fn to_exit_code(&self) -> i32 { 1 }
+            }
+
+            compile_error!("derive(ToExitCode) is only implemented for enums");
+        }.into(),
+    };
+    TokenStream::from(quote! {
+        impl ::wheel::traits::ToExitCode for #ty {
+            fn to_exit_code(&self) -> i32 {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
 /// Implements the `IsVerbose` trait for a struct with a `verbose: bool` field.
 ///
 /// This trait is used with `#[wheel::main(verbose_debug)]`.
@@ -217,19 +292,22 @@ pub fn lib(_: TokenStream, item: TokenStream) -> TokenStream {
 ///
 /// * Specify as `#[wheel::main(custom_exit)]` to handle the `main` function's return value using the `wheel::CustomExit` trait instead of `wheel::MainOutput`, allowing to customize error handling behavior.
 /// * Specify as `#[wheel::main(debug)]` to display the `Debug` output of the value returned from `main`. This is accomplished by passing `true` to the `debug` parameter of `wheel::MainOutput::exit`. This is the default and may be deprecated in the future.
+/// * Specify as `#[wheel::main(exit_codes)]` to exit with the status code given by `wheel::traits::ToExitCode` in case of an error, using the `wheel::ExitCodeMainOutput` trait instead of `wheel::MainOutput`. The error type must implement `ToExitCode`, which is the case for `wheel::Error` and can be derived for other error enums. This can be combined with `debug`, `no_debug`, or `verbose_debug`.
+/// * Specify as `#[wheel::main(json_errors)]` to print errors as a JSON object on a single line of stderr, using the `wheel::JsonMainOutput` trait instead of `wheel::MainOutput`. The error type must implement `serde::Serialize`, which is the case for `wheel::Error`. This requires the `wheel` crate feature `serde_json`.
 /// * Specify as `#[wheel::main(no_debug)]` to suppress the `Debug` output of the value returned from `main`.
 /// * Specify as `#[wheel::main(verbose_debug)]` to only enable `debug` behavior if `wheel::IsVerbose::is_verbose` returns `true` for the parsed command-line arguments.
 /// * Specify as `#[wheel::main(rocket)]` to initialize the async runtime using [`rocket::main`](https://docs.rs/rocket/0.5.0/rocket/attr.main.html) instead of [`tokio::main`](https://docs.rs/tokio/latest/tokio/attr.main.html). This requires the `wheel` crate feature `rocket`.
 /// * Specify as `#[wheel::main(console = port)]`, where `port` is a [`u16`] literal, to initialize [`console-subscriber`](https://docs.rs/console-subscriber) for Tokio console. Requires `cfg(tokio_unstable)`.
 /// * Specify as `#[wheel::main(max_blocking_threads = val)]`, where `val` is an [`i16`] literal, to configure the Tokio runtime's [`max_blocking_threads`](https://docs.rs/tokio/latest/tokio/runtime/struct.Builder.html#method.max_blocking_threads). A value less than one will be added to the [`available_parallelism`](https://doc.rust-lang.org/std/thread/fn.available_parallelism.html), e.g. specifying `#[wheel::main(max_blocking_threads = -1)]` when 16 cores are detected will configure Tokio with 15 `max_blocking_threads`.
 ///
-/// The `custom_exit`, `debug`, `no_debug`, and `verbose_debug` parameters are mutually exclusive, but otherwise parameters can be combined with each other, e.g. `#[wheel::main(no_debug, rocket, console = 6669)]`.
+/// The `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` parameters are mutually exclusive, but otherwise parameters can be combined with each other, e.g. `#[wheel::main(no_debug, rocket, console = 6669)]`.
 #[proc_macro_attribute]
 pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args with Punctuated::<Meta, Token![,]>::parse_terminated);
     let mut exit_trait = None;
     let mut debug = Some(true);
     let mut debug_arg = true;
+    let mut exit_codes = None;
     let mut use_rocket = false;
     let mut console_port = None::<u16>;
     let mut max_blocking_threads = None::<i16>;
@@ -259,7 +337,7 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             }
             if exit_trait.replace(quote!(::wheel::CustomExit)).is_some() {
                 return quote_spanned! {arg.span()=>
-                    compile_error!("parameters `custom_exit`, `debug`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
+                    compile_error!("parameters `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
                 }.into()
             }
             debug_arg = false;
@@ -269,10 +347,29 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             }
             if exit_trait.replace(quote!(::wheel::MainOutput)).is_some() {
                 return quote_spanned! {arg.span()=>
-                    compile_error!("parameters `custom_exit`, `debug`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
+                    compile_error!("parameters `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
                 }.into()
             }
             debug = Some(true);
+        } else if arg.path().is_ident("exit_codes") {
+            if let Err(e) = arg.require_path_only() {
+                return e.into_compile_error().into()
+            }
+            if exit_codes.replace(arg.span()).is_some() {
+                return quote_spanned! {arg.span()=>
+                    compile_error!("`#[wheel::main(exit_codes)]` specified multiple times");
+                }.into()
+            }
+        } else if arg.path().is_ident("json_errors") {
+            if let Err(e) = arg.require_path_only() {
+                return e.into_compile_error().into()
+            }
+            if exit_trait.replace(quote!(::wheel::JsonMainOutput)).is_some() {
+                return quote_spanned! {arg.span()=>
+                    compile_error!("parameters `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
+                }.into()
+            }
+            debug_arg = false;
         } else if arg.path().is_ident("max_blocking_threads") {
             match arg.require_name_value() {
                 Ok(MetaNameValue { value, .. }) => if let Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) = value {
@@ -298,7 +395,7 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             }
             if exit_trait.replace(quote!(::wheel::MainOutput)).is_some() {
                 return quote_spanned! {arg.span()=>
-                    compile_error!("parameters `custom_exit`, `debug`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
+                    compile_error!("parameters `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
                 }.into()
             }
             debug = Some(false);
@@ -318,7 +415,7 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             }
             if exit_trait.replace(quote!(::wheel::MainOutput)).is_some() {
                 return quote_spanned! {arg.span()=>
-                    compile_error!("parameters `custom_exit`, `debug`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
+                    compile_error!("parameters `custom_exit`, `debug`, `json_errors`, `no_debug`, and `verbose_debug` on `#[wheel::main]` are mutually exclusive");
                 }.into()
             }
             debug = None;
@@ -328,7 +425,16 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
             }.into()
         }
     }
-    let exit_trait = exit_trait.unwrap_or(quote!(::wheel::MainOutput));
+    let exit_trait = if let Some(span) = exit_codes {
+        if !debug_arg {
+            return quote_spanned! {span=>
+                compile_error!("parameter `exit_codes` on `#[wheel::main]` can't be combined with `custom_exit` or `json_errors`");
+            }.into()
+        }
+        quote!(::wheel::ExitCodeMainOutput)
+    } else {
+        exit_trait.unwrap_or(quote!(::wheel::MainOutput))
+    };
     let main_fn = parse_macro_input!(item as ItemFn);
     let asyncness = &main_fn.sig.asyncness;
     let (arg, parse_args, args) = match main_fn.sig.inputs.iter().at_most_one() {
@@ -2,6 +2,7 @@
 
 use {
     std::{
+        ffi::OsString,
         io::{
             self,
             IoSlice,
@@ -19,6 +20,7 @@ use {
             Context,
             Poll,
         },
+        time::SystemTime,
     },
     tokio::{
         fs::OpenOptions,
@@ -41,6 +43,13 @@ pub use {
     },
     tokio::fs::DirEntry,
 };
+#[cfg(unix)] use {
+    std::{
+        collections::HashSet,
+        os::unix::fs::MetadataExt as _,
+    },
+    nix::sys::statvfs::Statvfs,
+};
 #[cfg(feature = "futures")] use futures::stream::{
     self,
     Stream,
@@ -181,6 +190,23 @@ impl<'r> Responder<'r, 'static> for File {
     }
 }
 
+/// Specifies which previous versions of a file are kept by [`write_with_backup`] and [`write_json_with_backup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backups {
+    /// The previous version is kept as `<name>.bak`, replacing any older backup.
+    Single,
+    /// Previous versions are kept as `<name>.<timestamp>.bak`. Only the given number of most recent backups are kept, older ones are deleted. With `Timestamped(0)`, the backup is deleted right after it's made, so no previous version is kept.
+    Timestamped(usize),
+}
+
+#[cfg(unix)]
+/// Returns the number of bytes available to unprivileged users on the file system containing the given path.
+pub async fn available_space(path: impl AsRef<Path>) -> Result<u64> {
+    let path = path.as_ref().to_owned();
+    let stat = statvfs(path.clone()).await.at(&path)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
 /// A wrapper around [`tokio::fs::canonicalize`].
 pub async fn canonicalize(path: impl AsRef<Path>) -> Result<PathBuf> {
     let path = path.as_ref();
@@ -213,6 +239,30 @@ pub async fn create_dir_all(path: impl AsRef<Path>) -> Result {
     tokio::fs::create_dir_all(path).await.at(path)
 }
 
+/// Returns the total size in bytes of all files in the given directory and its subdirectories. Symlinks are not followed.
+///
+/// If `count_hard_links_once` is `true`, a file with multiple hard links inside the directory is only counted once. This has no effect on non-Unix platforms.
+pub async fn dir_size(path: impl AsRef<Path>, count_hard_links_once: bool) -> Result<u64> {
+    #[cfg(unix)] let mut seen_inodes = HashSet::<(u64, u64)>::new();
+    #[cfg(not(unix))] let _ = count_hard_links_once;
+    let mut size = 0;
+    let mut stack = vec![path.as_ref().to_owned()];
+    while let Some(dir) = stack.pop() {
+        let mut read_dir = tokio::fs::read_dir(&dir).await.at(&dir)?;
+        while let Some(entry) = read_dir.next_entry().await.at(&dir)? {
+            let path = entry.path();
+            let metadata = tokio::fs::symlink_metadata(&path).await.at(&path)?;
+            if metadata.is_dir() {
+                stack.push(path);
+            } else {
+                #[cfg(unix)] if count_hard_links_once && metadata.nlink() > 1 && !seen_inodes.insert((metadata.dev(), metadata.ino())) { continue }
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
 /// A wrapper around [`tokio::fs::try_exists`].
 pub async fn exists(path: impl AsRef<Path>) -> Result<bool> {
     let path = path.as_ref();
@@ -331,6 +381,14 @@ pub async fn symlink_metadata(path: impl AsRef<Path>) -> Result<Metadata> {
     tokio::fs::symlink_metadata(path).await.at(path)
 }
 
+#[cfg(unix)]
+/// Returns the total size in bytes of the file system containing the given path.
+pub async fn total_space(path: impl AsRef<Path>) -> Result<u64> {
+    let path = path.as_ref().to_owned();
+    let stat = statvfs(path.clone()).await.at(&path)?;
+    Ok(stat.blocks() as u64 * stat.fragment_size() as u64)
+}
+
 /// A wrapper around [`tokio::fs::write`].
 pub async fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result {
     let path = path.as_ref();
@@ -343,6 +401,85 @@ pub async fn write_new(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Re
     File::create_new(path).await?.write_all(contents.as_ref()).await.at(path)
 }
 
+/// Atomically replaces the contents of the file at `path`, keeping a copy of its previous contents as specified by `backups`.
+///
+/// The new contents are first written to a temporary file with a unique name in the same directory, then moved into place, so the file at `path` always has either its old or its new contents. The new file gets the permissions of the file it replaces. If the file does not exist yet, no backup is created.
+///
+/// The backup is a copy of the old file rather than the old file itself, since moving the old file away would leave a moment where no file exists at `path`.
+///
+/// If `path` is a symlink, the file it points to is replaced and the backups are created next to that file, so the symlink stays intact.
+pub async fn write_with_backup(path: impl AsRef<Path>, contents: impl AsRef<[u8]>, backups: Backups) -> Result {
+    let path = match tokio::fs::canonicalize(path.as_ref()).await {
+        Ok(path) => path,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.as_ref().to_owned(),
+        Err(e) => return Err(e).at(path),
+    };
+    let path = &*path;
+    let permissions = match tokio::fs::metadata(path).await {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).at(path),
+    };
+    let mut tmp_file = create_tmp(path).await?;
+    let tmp_path = tmp_file.path.clone();
+    let result = async {
+        if let Some(permissions) = permissions.clone() {
+            tmp_file.inner.set_permissions(permissions).await.at(&tmp_path)?;
+        }
+        tmp_file.write_all(contents.as_ref()).await.at(&tmp_path)?;
+        tmp_file.sync_all().await?;
+        drop(tmp_file);
+        if permissions.is_some() {
+            match backups {
+                Backups::Single => { copy(path, with_suffix(path, ".bak")).await?; }
+                Backups::Timestamped(keep) => {
+                    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+                    copy(path, with_suffix(path, &format!(".{timestamp}.bak"))).await?;
+                    let dir = match path.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => parent,
+                        _ => Path::new("."),
+                    };
+                    let prefix = path.file_name().map(|name| format!("{}.", name.to_string_lossy())).unwrap_or_default();
+                    let mut existing = Vec::default();
+                    let mut read_dir = tokio::fs::read_dir(dir).await.at(dir)?;
+                    while let Some(entry) = read_dir.next_entry().await.at(dir)? {
+                        if let Some(timestamp) = entry.file_name().to_str()
+                            .and_then(|name| name.strip_prefix(&prefix))
+                            .and_then(|name| name.strip_suffix(".bak"))
+                            .and_then(|timestamp| timestamp.parse::<u128>().ok())
+                        {
+                            existing.push((timestamp, entry.path()));
+                        }
+                    }
+                    existing.sort_unstable();
+                    for (_, old_backup) in existing.iter().rev().skip(keep) {
+                        remove_file(old_backup).await?;
+                    }
+                }
+            }
+        }
+        rename(&tmp_path, path).await
+    }.await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+/// Creates a file with a name that isn't used by any other file in the same directory as `path`, for contents which will then be moved to `path`.
+async fn create_tmp(path: &Path) -> Result<File> {
+    let pid = std::process::id();
+    loop {
+        let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+        let tmp_path = with_suffix(path, &format!(".{pid}.{nanos}.tmp"));
+        match tokio::fs::File::create_new(&tmp_path).await {
+            Ok(inner) => break Ok(File { inner, path: tmp_path }),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => break Err(e).at(tmp_path),
+        }
+    }
+}
+
 #[cfg(feature = "serde_json")]
 /// A convenience method for serializing and writing a JSON file with proper indentation and a trailing newline.
 pub async fn write_json(path: impl AsRef<Path>, value: impl Serialize) -> Result {
@@ -364,3 +501,25 @@ pub async fn write_json_new(path: impl AsRef<Path>, value: impl Serialize) -> Re
     buf.push(b'\n');
     write_new(path, buf).await
 }
+
+#[cfg(feature = "serde_json")]
+/// Like [`write_json`] but atomically replaces the file and keeps a copy of its previous contents. See [`write_with_backup`] for details.
+pub async fn write_json_with_backup(path: impl AsRef<Path>, value: impl Serialize, backups: Backups) -> Result {
+    let path = path.as_ref();
+    let mut serializer = serde_json::Serializer::with_formatter(Vec::default(), serde_json::ser::PrettyFormatter::with_indent(b"    "));
+    value.serialize(&mut serializer).at(path)?;
+    let mut buf = serializer.into_inner();
+    buf.push(b'\n');
+    write_with_backup(path, buf, backups).await
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(unix)]
+async fn statvfs(path: PathBuf) -> io::Result<Statvfs> {
+    tokio::task::spawn_blocking(move || nix::sys::statvfs::statvfs(&path).map_err(io::Error::from)).await?
+}
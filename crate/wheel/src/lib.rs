@@ -7,7 +7,10 @@ use {
         convert::Infallible as Never,
         fmt,
         io,
+        iter,
+        net::SocketAddr,
         path::PathBuf,
+        sync::Arc,
     },
     itertools::Itertools as _,
     thiserror::Error,
@@ -19,27 +22,21 @@ pub use wheel_derive::{
     lib,
     main,
 };
+#[cfg(unix)] use {
+    std::os::unix::process::ExitStatusExt as _,
+    nix::sys::signal::Signal,
+};
 #[cfg(feature = "night")] use {
-    std::{
-        io::prelude::*,
-        process::Stdio,
-    },
+    std::process::Stdio,
     noisy_float::prelude::*,
     crate::traits::{
         IoResultExt as _,
         SyncCommandOutputExt as _,
     },
 };
-#[cfg(feature = "pyo3")] use pyo3::{
-    exceptions::PyException,
-    prelude::*,
-};
 #[cfg(all(feature = "night", feature = "tokio"))]
 use {
-    tokio::{
-        io::AsyncWriteExt as _,
-        process::Command,
-    },
+    tokio::process::Command,
     crate::traits::AsyncCommandOutputExt as _,
 };
 
@@ -55,6 +52,10 @@ use {
 #[cfg(feature = "tokio")] pub mod fs;
 #[cfg(feature = "github")] pub mod github;
 #[cfg(feature = "gui")] pub mod gui;
+#[cfg(all(feature = "futures", feature = "tokio"))] pub mod process;
+#[cfg(feature = "pyo3")] pub mod python;
+pub mod retry;
+pub mod sysexits;
 pub mod traits;
 
 /// Prints the given prompt to stdout, then reads and returns a line from stdin.
@@ -90,6 +91,7 @@ pub mod traits;
 
 /// Used in [`Error::Io`] as metadata for where the error occurred.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde_json", derive(serde::Serialize), serde(tag = "type", content = "value", rename_all = "snake_case"))]
 pub enum IoErrorContext {
     /// The error was not annotated with any context.
     Unknown,
@@ -98,7 +100,23 @@ pub enum IoErrorContext {
     /// The error occurred while working with the two given paths.
     DoublePath(PathBuf, PathBuf),
     /// The error occurred while trying to run a command with the given name.
+    ///
+    /// The name can be derived from the command itself using [`CommandDisplay`].
     Command(Cow<'static, str>),
+    #[cfg(feature = "url")]
+    /// The error occurred while working with the given URL.
+    Url(url::Url),
+    /// The error occurred while reading the environment variable with the given name.
+    EnvVar(String),
+    /// The error occurred while working with the given key in the given configuration file.
+    ConfigKey {
+        /// The path to the configuration file.
+        file: PathBuf,
+        /// The key within the configuration file, e.g. a dotted path like `server.port`.
+        key: String,
+    },
+    /// The error occurred while working with a socket connected or bound to the given address.
+    Socket(SocketAddr),
 }
 
 impl fmt::Display for IoErrorContext {
@@ -108,8 +126,69 @@ impl fmt::Display for IoErrorContext {
             Self::Path(path) => write!(f, "I/O error at {}", path.display()),
             Self::DoublePath(src, dst) => write!(f, "I/O error at {} and {}", src.display(), dst.display()),
             Self::Command(name) => write!(f, "in command `{name}`"),
+            #[cfg(feature = "url")] Self::Url(url) => write!(f, "I/O error at {url}"),
+            Self::EnvVar(name) => write!(f, "I/O error in environment variable {name}"),
+            Self::ConfigKey { file, key } => write!(f, "I/O error at key {key} in {}", file.display()),
+            Self::Socket(addr) => write!(f, "I/O error at {addr}"),
+        }
+    }
+}
+
+/// A shell-quoted representation of a command's program and arguments, for use as a command name in errors.
+///
+/// This implements `Into<Cow<'static, str>>`, so it can be passed to `check`, `exec`, or [`at_command`](traits::IoResultExt::at_command) in place of a hand-written name.
+#[derive(Debug, Clone)]
+pub struct CommandDisplay {
+    /// The program followed by its arguments. Redacted arguments are `None`.
+    words: Vec<Option<String>>,
+}
+
+impl CommandDisplay {
+    /// Creates a representation of the given command's program and arguments.
+    pub fn new(cmd: &std::process::Command) -> Self {
+        Self {
+            words: iter::once(cmd.get_program()).chain(cmd.get_args()).map(|word| Some(word.to_string_lossy().into_owned())).collect(),
         }
     }
+
+    /// Hides the argument at the given index (starting at 0 for the first argument after the program), e.g. because it contains a secret.
+    pub fn redact(mut self, arg_index: usize) -> Self {
+        if let Some(word) = self.words.get_mut(arg_index + 1) {
+            *word = None;
+        }
+        self
+    }
+}
+
+impl fmt::Display for CommandDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, word) in self.words.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            if let Some(word) = word {
+                write!(f, "{}", shell_quote(word))?;
+            } else {
+                write!(f, "<redacted>")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<CommandDisplay> for Cow<'static, str> {
+    fn from(display: CommandDisplay) -> Self {
+        Self::Owned(display.to_string())
+    }
+}
+
+/// Quotes a word for a POSIX shell, if necessary.
+pub(crate) fn shell_quote(word: &str) -> Cow<'_, str> {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c)) {
+        Cow::Borrowed(word)
+    } else {
+        Cow::Owned(format!("'{}'", word.replace('\'', "'\\''")))
+    }
 }
 
 /// An error that can be returned from the [traits] in this crate.
@@ -121,19 +200,82 @@ pub enum Error {
     #[cfg(all(feature = "chrono", feature = "reqwest"))] #[error(transparent)] HeaderToStr(#[from] reqwest::header::ToStrError),
     #[cfg(all(feature = "chrono", feature = "reqwest"))] #[error(transparent)] ParseInt(#[from] std::num::ParseIntError),
     /// A subprocess exited with a non-success status. Output information is available.
-    #[error("command `{name}` exited with {}", .output.status)]
+    #[error("command `{name}` {}{}", describe_status(.output.status), describe_stderr(&.output.stderr))]
     CommandExit {
         /// The name of the subprocess, as indicated by the `check` call.
         name: Cow<'static, str>,
         output: std::process::Output,
     },
     /// A subprocess exited with a non-success status. Output information is unavailable.
-    #[error("command `{name}` exited with {}", .status)]
+    #[error("command `{name}` {}", describe_status(*.status))]
     CommandExitStatus {
         /// The name of the subprocess, as indicated by the `check` call.
         name: Cow<'static, str>,
         status: std::process::ExitStatus,
     },
+    /// A subprocess run using `check_pty` exited with a non-success status. Its combined terminal output is available.
+    #[cfg(unix)]
+    #[error("command `{name}` {}{}", describe_status(.output.status), describe_terminal_output(&.output.stripped()))]
+    CommandPtyExit {
+        /// The name of the subprocess, as indicated by the `check_pty` call.
+        name: Cow<'static, str>,
+        output: traits::PtyOutput,
+    },
+    /// A subprocess exited successfully but its stdout could not be parsed as JSON.
+    #[cfg(feature = "serde_json")]
+    #[error("command `{name}` produced invalid JSON: {inner}, stdout:\n\n{excerpt}")]
+    CommandJson {
+        /// The name of the subprocess, as indicated by the `check_json` call.
+        name: Cow<'static, str>,
+        #[source]
+        inner: serde_json_path_to_error::Error,
+        /// The beginning of the subprocess's stdout, decoded lossily.
+        excerpt: String,
+    },
+    /// A subprocess run using `check_with_retry` failed on every attempt.
+    #[error("command `{name}` failed {} times{}", .attempts.len(), if let Some(last) = .attempts.last() { format!(", last error: {last}") } else { String::default() })]
+    CommandRetries {
+        /// The name of the subprocess, as indicated by the `check_with_retry` call.
+        name: Cow<'static, str>,
+        /// The errors from each attempt, in order.
+        attempts: Vec<Error>,
+    },
+    /// A subprocess was killed because it didn't exit within the time limit given to `check_timeout`.
+    #[error("command `{name}` timed out")]
+    CommandTimeout {
+        /// The name of the subprocess, as indicated by the `check_timeout` call.
+        name: Cow<'static, str>,
+        /// The output captured before the subprocess was killed, along with the exit status after being killed.
+        partial_output: std::process::Output,
+    },
+    /// A subprocess exited successfully but its stdout was not valid UTF-8.
+    #[error("command `{name}` produced invalid UTF-8: {inner}, stdout:\n\n{excerpt}")]
+    CommandUtf8 {
+        /// The name of the subprocess, as indicated by the `check_stdout_string` or `check_stdout_lines` call.
+        name: Cow<'static, str>,
+        #[source]
+        inner: std::str::Utf8Error,
+        /// The beginning of the subprocess's stdout, decoded lossily.
+        excerpt: String,
+    },
+    /// Some of the subprocesses run using `run_all` failed.
+    #[error("{} of the commands failed{}", .failures.len(), describe_failures(.failures, .skipped))]
+    CommandsFailed {
+        /// The names and outputs of the commands which succeeded, in the order the commands were given.
+        succeeded: Vec<(Cow<'static, str>, std::process::Output)>,
+        /// The errors from each failed subprocess, in the order the commands were given.
+        failures: Vec<Error>,
+        /// The names of the commands which were cancelled or never started because another command failed and `run_all` was called with [`process::FailureMode::FailFast`].
+        skipped: Vec<Cow<'static, str>>,
+    },
+    /// An error annotated using [`traits::ResultContextExt`].
+    #[error("{}", describe_context(.frames, .inner))]
+    Context {
+        /// Descriptions of what was being done when the error occurred, innermost first.
+        frames: Vec<String>,
+        #[source]
+        inner: Box<Error>,
+    },
     #[cfg(all(feature = "chrono", feature = "reqwest"))]
     #[error("x-ratelimit-reset header is out of range for chrono::DateTime")]
     InvalidDateTime,
@@ -188,16 +330,252 @@ pub enum Error {
         headers: reqwest::header::HeaderMap,
         text: reqwest::Result<String>,
     },
+    /// A [`SharedError`] which couldn't be unwrapped because other clones of it still exist.
+    #[error(transparent)]
+    Shared(SharedError),
+    /// A subprocess run using [`traits::CommandExt::sudo`], [`traits::CommandExt::as_user`], or [`traits::CommandExt::sudo_with_options`] failed because `sudo` would have prompted for a password.
+    #[error("command `{name}` failed because sudo requires a password")]
+    SudoPasswordRequired {
+        /// The name of the subprocess, as indicated by the `check` call.
+        name: Cow<'static, str>,
+        /// The output of `sudo`.
+        output: std::process::Output,
+    },
     #[cfg(all(feature = "chrono", feature = "reqwest"))]
     #[error("attempted to send GitHub API request with streamed body")]
     UncloneableGitHubRequest,
 }
 
-#[cfg(feature = "pyo3")]
-impl From<Error> for PyErr {
+impl Error {
+    /// Annotates this error with a description of what was being done when it occurred. See [`traits::ResultContextExt`].
+    pub fn context(self, context: impl ToString) -> Self {
+        match self {
+            Self::Context { mut frames, inner } => {
+                frames.push(context.to_string());
+                Self::Context { frames, inner }
+            }
+            inner => Self::Context {
+                frames: vec![context.to_string()],
+                inner: Box::new(inner),
+            },
+        }
+    }
+
+    /// If this error represents a subprocess that exited with a non-success status, returns that status.
+    pub fn exit_status(&self) -> Option<std::process::ExitStatus> {
+        match self {
+            Self::CommandExit { output, .. } | Self::SudoPasswordRequired { output, .. } => Some(output.status),
+            Self::CommandExitStatus { status, .. } => Some(*status),
+            #[cfg(unix)] Self::CommandPtyExit { output, .. } => Some(output.status),
+            Self::Context { inner, .. } => inner.exit_status(),
+            Self::Shared(e) => e.exit_status(),
+            _ => None,
+        }
+    }
+
+    /// If this error represents a subprocess that exited with a non-success exit code, returns that code.
+    ///
+    /// Returns `None` if the subprocess was terminated by a signal.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_status()?.code()
+    }
+
+    #[cfg(unix)]
+    /// If this error represents a subprocess that was terminated by a signal, returns that signal.
+    pub fn signal(&self) -> Option<Signal> {
+        Signal::try_from(self.exit_status()?.signal()?).ok()
+    }
+
+    /// If this error represents a subprocess whose stderr was captured, returns it, decoded lossily.
+    pub fn stderr_lossy(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::CommandExit { output, .. } | Self::SudoPasswordRequired { output, .. } => Some(String::from_utf8_lossy(&output.stderr)),
+            Self::CommandTimeout { partial_output, .. } => Some(String::from_utf8_lossy(&partial_output.stderr)),
+            Self::Context { inner, .. } => inner.stderr_lossy(),
+            Self::Shared(e) => e.stderr_lossy(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl Error {
+    /// The I/O context this error was annotated with, if any.
+    fn io_context(&self) -> Option<&IoErrorContext> {
+        match self {
+            Self::Context { inner, .. } => inner.io_context(),
+            Self::Shared(e) => e.io_context(),
+            Self::Io { context, .. } | Self::Json { context, .. } | Self::JsonPathToError { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The top-level message of this error, followed by the messages of its causes.
+    fn message_chain(&self) -> Vec<String> {
+        if let Self::Context { frames, inner } = self {
+            frames.iter().rev().cloned().chain(inner.message_chain()).collect()
+        } else if let Self::Shared(e) = self {
+            e.message_chain()
+        } else {
+            let mut chain = vec![self.to_string()];
+            let mut source = std::error::Error::source(self);
+            while let Some(e) = source {
+                chain.push(e.to_string());
+                source = e.source();
+            }
+            chain
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+/// Serializes a summary of the error for machine-readable output, such as from `#[wheel::main(json_errors)]`.
+///
+/// The result is an object with the fields `message` (the top-level error message), `chain` (the messages of the underlying causes, outermost first), `context` (the [`IoErrorContext`], if any), and `exit_code` (see [`traits::ToExitCode`]).
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct as _;
+
+        let mut chain = self.message_chain();
+        let message = chain.remove(0);
+        let mut state = serializer.serialize_struct("Error", 4)?;
+        state.serialize_field("message", &message)?;
+        state.serialize_field("chain", &chain)?;
+        state.serialize_field("context", &self.io_context())?;
+        state.serialize_field("exit_code", &traits::ToExitCode::to_exit_code(self))?;
+        state.end()
+    }
+}
+
+/// A cloneable version of [`enum@Error`], for reporting the same failure to multiple places, e.g. when broadcasting it to several tasks.
+///
+/// This dereferences to the underlying [`enum@Error`] and behaves like it with regard to [`Display`](fmt::Display), [`source`](std::error::Error::source), [`traits::IsNetworkError`], and [`traits::ToExitCode`].
+/// Converting back into an [`enum@Error`] unwraps it if there are no other clones, or produces [`Error::Shared`] otherwise.
+#[derive(Debug, Clone)]
+pub struct SharedError(Arc<Error>);
+
+impl SharedError {
+    /// Converts back into an [`enum@Error`] if this is the only clone, or returns `self` otherwise.
+    pub fn try_unwrap(self) -> std::result::Result<Error, Self> {
+        Arc::try_unwrap(self.0).map_err(Self)
+    }
+}
+
+impl std::ops::Deref for SharedError {
+    type Target = Error;
+
+    fn deref(&self) -> &Error {
+        &self.0
+    }
+}
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SharedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl From<Error> for SharedError {
     fn from(e: Error) -> Self {
-        PyException::new_err(e.to_string()) //TODO different exception classes for different Error variants?
+        match e {
+            Error::Shared(e) => e,
+            e => Self(Arc::new(e)),
+        }
+    }
+}
+
+impl From<SharedError> for Error {
+    fn from(e: SharedError) -> Self {
+        e.try_unwrap().unwrap_or_else(Self::Shared)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl serde::Serialize for SharedError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+/// The maximum number of bytes of a subprocess's stderr included in the `Display` output of [`Error::CommandExit`].
+const STDERR_TAIL_LEN: usize = 1024;
+
+/// Describes how a subprocess exited, for use in an error message.
+fn describe_status(status: std::process::ExitStatus) -> String {
+    #[cfg(unix)] if let Some(signal) = status.signal() {
+        let signal = match Signal::try_from(signal) {
+            Ok(Signal::SIGXCPU) => "SIGXCPU (CPU time limit exceeded)".to_owned(),
+            Ok(Signal::SIGXFSZ) => "SIGXFSZ (file size limit exceeded)".to_owned(),
+            Ok(signal) => signal.as_str().to_owned(),
+            Err(_) => format!("signal {signal}"),
+        };
+        return if status.core_dumped() {
+            format!("was killed by {signal} (core dumped)")
+        } else {
+            format!("was killed by {signal}")
+        }
+    }
+    if let Some(code) = status.code() {
+        format!("exited with code {code}")
+    } else {
+        format!("exited with {status}")
+    }
+}
+
+/// Formats the end of a subprocess's stderr for use in an error message.
+fn describe_stderr(stderr: &[u8]) -> String {
+    describe_output_tail("stderr", stderr)
+}
+
+#[cfg(unix)]
+/// Formats the end of a subprocess's terminal output for inclusion in an error message.
+fn describe_terminal_output(output: &[u8]) -> String {
+    describe_output_tail("terminal output", output)
+}
+
+fn describe_output_tail(label: &str, output: &[u8]) -> String {
+    let output = String::from_utf8_lossy(output);
+    let output = output.trim_end();
+    if output.is_empty() {
+        String::default()
+    } else if output.len() > STDERR_TAIL_LEN {
+        let mut start = output.len() - STDERR_TAIL_LEN;
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        format!(", {label}:\n\n…{}", &output[start..])
+    } else {
+        format!(", {label}:\n\n{output}")
+    }
+}
+
+/// Formats an error annotated with context frames as a "caused by" chain, outermost frame first.
+fn describe_context(frames: &[String], inner: &Error) -> String {
+    let mut description = String::default();
+    for frame in frames.iter().rev() {
+        description.push_str(frame);
+        description.push_str("\ncaused by: ");
     }
+    description.push_str(&inner.to_string());
+    description
+}
+
+/// Lists the failed and skipped commands from `run_all`, for use in an error message.
+fn describe_failures(failures: &[Error], skipped: &[Cow<'static, str>]) -> String {
+    let mut description = String::default();
+    for failure in failures {
+        description.push_str(&format!("\n- {failure}"));
+    }
+    if !skipped.is_empty() {
+        description.push_str(&format!("\n{} other commands were cancelled: {}", skipped.len(), skipped.iter().map(|name| format!("`{name}`")).collect::<Vec<_>>().join(", ")));
+    }
+    description
 }
 
 /// A shorthand for a result with defaults for both variants (unit and this crate's [`enum@Error`], respectively).
@@ -264,12 +642,129 @@ impl<T: MainOutput, E: fmt::Debug + fmt::Display> MainOutput for Result<T, E> {
     }
 }
 
+/// Use this trait together with an `exit_codes` argument on [`main`] to exit with the status code given by [`traits::ToExitCode`] in case of an error, rather than always using status code 1.
+pub trait ExitCodeMainOutput {
+    /// Exits from the program using this value, displaying it and the given command name (usually `CARGO_PKG_NAME`) in case of an error.
+    fn exit(self, cmd_name: &'static str, debug: bool) -> !;
+}
+
+impl ExitCodeMainOutput for Never {
+    fn exit(self, _: &'static str, _: bool) -> ! {
+        match self {}
+    }
+}
+
+impl ExitCodeMainOutput for () {
+    fn exit(self, cmd_name: &'static str, debug: bool) -> ! {
+        MainOutput::exit(self, cmd_name, debug)
+    }
+}
+
+impl ExitCodeMainOutput for bool {
+    fn exit(self, cmd_name: &'static str, debug: bool) -> ! {
+        MainOutput::exit(self, cmd_name, debug)
+    }
+}
+
+impl ExitCodeMainOutput for i32 {
+    fn exit(self, cmd_name: &'static str, debug: bool) -> ! {
+        MainOutput::exit(self, cmd_name, debug)
+    }
+}
+
+impl ExitCodeMainOutput for std::process::ExitStatus {
+    fn exit(self, cmd_name: &'static str, debug: bool) -> ! {
+        MainOutput::exit(self, cmd_name, debug)
+    }
+}
+
+impl<T: ExitCodeMainOutput, E: fmt::Debug + fmt::Display + traits::ToExitCode> ExitCodeMainOutput for Result<T, E> {
+    fn exit(self, cmd_name: &'static str, debug: bool) -> ! {
+        match self {
+            Ok(x) => x.exit(cmd_name, debug),
+            Err(e) => {
+                eprintln!("{cmd_name}: {e}");
+                if debug {
+                    eprintln!("debug info: {e:?}");
+                }
+                std::process::exit(e.to_exit_code())
+            }
+        }
+    }
+}
+
 /// Implement this trait for your `main` arguments to use the `verbose_debug` argument on [`main`].
 pub trait IsVerbose {
     /// Returns whether a `--verbose` argument is present.
     fn is_verbose(&self) -> bool;
 }
 
+#[cfg(feature = "serde_json")]
+/// Use this trait together with a `json_errors` argument on [`main`] to print errors as JSON objects, for consumption by other programs.
+///
+/// In case of an error, a single line is printed to stderr containing an object with the fields `command` (the given command name), `message` (the error's `Display` output), `exit_code` (see [`traits::ToExitCode`]), and `error` (the error serialized using its `Serialize` implementation, or the `Display` output if serialization fails; see [`Error`'s implementation](Error#impl-Serialize-for-Error)).
+pub trait JsonMainOutput {
+    /// Exits from the program using this value, printing it as JSON along with the given command name (usually `CARGO_PKG_NAME`) in case of an error.
+    fn exit(self, cmd_name: &'static str) -> !;
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonMainOutput for Never {
+    fn exit(self, _: &'static str) -> ! {
+        match self {}
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonMainOutput for () {
+    fn exit(self, cmd_name: &'static str) -> ! {
+        MainOutput::exit(self, cmd_name, false)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonMainOutput for bool {
+    fn exit(self, cmd_name: &'static str) -> ! {
+        MainOutput::exit(self, cmd_name, false)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonMainOutput for i32 {
+    fn exit(self, cmd_name: &'static str) -> ! {
+        MainOutput::exit(self, cmd_name, false)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonMainOutput for std::process::ExitStatus {
+    fn exit(self, cmd_name: &'static str) -> ! {
+        MainOutput::exit(self, cmd_name, false)
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl<T: JsonMainOutput, E: fmt::Display + serde::Serialize + traits::ToExitCode> JsonMainOutput for Result<T, E> {
+    fn exit(self, cmd_name: &'static str) -> ! {
+        match self {
+            Ok(x) => x.exit(cmd_name),
+            Err(e) => {
+                let exit_code = e.to_exit_code();
+                let message = e.to_string();
+                // fall back to the message if the error can't be represented as JSON, e.g. a map with non-string keys
+                let error = serde_json::to_value(&e).unwrap_or_else(|_| serde_json::Value::String(message.clone()));
+                eprintln!("{}", serde_json::json!({
+                    "command": cmd_name,
+                    "message": message,
+                    "exit_code": exit_code,
+                    "error": error,
+                }));
+                std::process::exit(exit_code)
+            }
+        }
+    }
+}
+
 /// Use this trait together with a `custom_exit` argument on [`main`] to customize the behavior of the program when exiting with an error.
 pub trait CustomExit {
     /// Exits from the program using this value, displaying it and the given command name (usually `CARGO_PKG_NAME`) in case of an error.
@@ -338,11 +833,12 @@ pub async fn night_report(path: &str, extra: Option<&str>) -> Result<std::proces
     if extra.is_some() {
         cmd.stdin(Stdio::piped());
     }
-    let mut child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+    let child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
     if let Some(extra) = extra {
-        child.stdin.take().expect("configured above").write_all(extra.as_ref()).await.at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+        child.check_with_stdin("sudo -u fenhl /opt/night/bin/nightd report", extra).await
+    } else {
+        child.check("sudo -u fenhl /opt/night/bin/nightd report").await
     }
-    child.check("sudo -u fenhl /opt/night/bin/nightd report").await
 }
 
 #[cfg(all(feature = "night", feature = "tokio"))]
@@ -355,11 +851,12 @@ pub async fn night_report_priority(path: &str, extra: Option<&str>, base_priorit
     if extra.is_some() {
         cmd.stdin(Stdio::piped());
     }
-    let mut child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+    let child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
     if let Some(extra) = extra {
-        child.stdin.take().expect("configured above").write_all(extra.as_ref()).await.at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+        child.check_with_stdin("sudo -u fenhl /opt/night/bin/nightd report", extra).await
+    } else {
+        child.check("sudo -u fenhl /opt/night/bin/nightd report").await
     }
-    child.check("sudo -u fenhl /opt/night/bin/nightd report").await
 }
 
 #[cfg(feature = "night")]
@@ -372,11 +869,12 @@ pub fn night_report_sync(path: &str, extra: Option<&str>) -> Result<std::process
     if extra.is_some() {
         cmd.stdin(Stdio::piped());
     }
-    let mut child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+    let child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
     if let Some(extra) = extra {
-        child.stdin.take().expect("configured above").write_all(extra.as_ref()).at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+        child.check_with_stdin("sudo -u fenhl /opt/night/bin/nightd report", extra)
+    } else {
+        child.check("sudo -u fenhl /opt/night/bin/nightd report")
     }
-    child.check("sudo -u fenhl /opt/night/bin/nightd report")
 }
 
 #[cfg(feature = "night")]
@@ -389,9 +887,10 @@ pub fn night_report_priority_sync(path: &str, extra: Option<&str>, base_priority
     if extra.is_some() {
         cmd.stdin(Stdio::piped());
     }
-    let mut child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+    let child = cmd.spawn().at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
     if let Some(extra) = extra {
-        child.stdin.take().expect("configured above").write_all(extra.as_ref()).at_command("sudo -u fenhl /opt/night/bin/nightd report")?;
+        child.check_with_stdin("sudo -u fenhl /opt/night/bin/nightd report", extra)
+    } else {
+        child.check("sudo -u fenhl /opt/night/bin/nightd report")
     }
-    child.check("sudo -u fenhl /opt/night/bin/nightd report")
 }
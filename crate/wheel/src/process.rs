@@ -0,0 +1,648 @@
+//! Utilities for running subprocesses, complementing the command extension traits in [`crate::traits`].
+
+use {
+    std::{
+        borrow::Cow,
+        convert::Infallible,
+        ffi::{
+            OsStr,
+            OsString,
+        },
+        io,
+        path::PathBuf,
+        process::{
+            ExitStatus,
+            Output,
+            Stdio,
+        },
+        sync::{
+            Arc,
+            Mutex,
+            PoisonError,
+        },
+        time::Duration,
+    },
+    async_trait::async_trait,
+    futures::{
+        future,
+        stream::{
+            self,
+            StreamExt as _,
+        },
+    },
+    tokio::process::Command,
+    crate::{
+        CommandDisplay,
+        Error,
+        Result,
+        shell_quote,
+        traits::{
+            AsyncCommandOutputExt as _,
+            IoResultExt as _,
+            OutputLine,
+            SyncCommandOutputExt as _,
+        },
+    },
+};
+#[cfg(unix)] use {
+    std::collections::HashSet,
+    nix::{
+        errno::Errno,
+        sys::signal::{
+            Signal,
+            killpg,
+        },
+        unistd::Pid,
+    },
+    tokio::{
+        process::Child,
+        signal::unix::{
+            SignalKind,
+            signal,
+        },
+        task::JoinHandle,
+        time::{
+            Instant,
+            sleep,
+        },
+    },
+    crate::traits::{
+        KILL_GRACE_PERIOD,
+        POLL_INTERVAL,
+    },
+};
+
+/// A sequence of commands where each command's stdout is connected to the next command's stdin, like `a | b | c` in a shell.
+///
+/// Each stage has its own name for error reporting.
+#[derive(Debug)]
+pub struct Pipeline {
+    stages: Vec<(Cow<'static, str>, Command)>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline consisting of the given command. The first command's stdin is used as configured.
+    pub fn new(name: impl Into<Cow<'static, str>>, cmd: Command) -> Self {
+        Self { stages: vec![(name.into(), cmd)] }
+    }
+
+    /// Appends a command to the pipeline, connecting the previous command's stdout to its stdin.
+    pub fn pipe(mut self, name: impl Into<Cow<'static, str>>, cmd: Command) -> Self {
+        self.stages.push((name.into(), cmd));
+        self
+    }
+
+    /// Runs all commands in the pipeline and waits for them to exit.
+    ///
+    /// Like a shell with `pipefail` set, this errors if any stage exits with a non-success status. If multiple stages fail, the [`Error::CommandExit`] refers to the last of them.
+    ///
+    /// On success, returns the last stage's exit status and stdout. The returned stderr is the concatenation of all stages' stderr, in pipeline order.
+    pub async fn check(self) -> Result<Output> {
+        let mut outputs = if let Some(runner) = current_runner() {
+            runner.pipeline(self).await?
+        } else {
+            self.run().await?
+        };
+        let stderr = outputs.iter().flat_map(|(_, output)| &output.stderr).copied().collect();
+        if let Some(idx) = outputs.iter().rposition(|(_, output)| !output.status.success()) {
+            let (name, output) = outputs.swap_remove(idx);
+            return Err(Error::CommandExit { name, output })
+        }
+        let (_, last_output) = outputs.pop().expect("pipeline has at least one stage");
+        Ok(Output { stderr, ..last_output })
+    }
+
+    /// Spawns all stages connected to each other and returns each stage's name and output, in pipeline order.
+    async fn run(self) -> Result<Vec<(Cow<'static, str>, Output)>> {
+        let last_idx = self.stages.len() - 1;
+        let mut children = Vec::with_capacity(self.stages.len());
+        let mut prev_stdout = None::<Stdio>;
+        for (idx, (name, mut cmd)) in self.stages.into_iter().enumerate() {
+            if let Some(stdin) = prev_stdout.take() {
+                cmd.stdin(stdin);
+            }
+            // make sure earlier stages don't keep running if a later stage fails to spawn
+            let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true).spawn().at_command(name.clone())?;
+            if idx < last_idx {
+                prev_stdout = Some(TryInto::<Stdio>::try_into(child.stdout.take().expect("configured above")).at_command(name.clone())?);
+            }
+            children.push((name, child));
+        }
+        future::try_join_all(children.into_iter().map(|(name, child)| async move {
+            let output = child.wait_with_output().await.at_command(name.clone())?;
+            Ok((name, output))
+        })).await
+    }
+}
+
+/// Whether [`run_all`] keeps going after a command fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Kill any running commands and don't start any more as soon as one fails.
+    FailFast,
+    /// Run every command, even if some of them fail.
+    RunToCompletion,
+}
+
+/// Runs the given commands using [`AsyncCommandOutputExt::check`](crate::traits::AsyncCommandOutputExt::check), with at most `concurrency` of them running at the same time.
+///
+/// If all commands succeed, their outputs are returned in the order the commands were given. Otherwise, returns [`Error::CommandsFailed`] listing every command that failed, as well as any that were skipped due to [`FailureMode::FailFast`]. The outputs of the commands which succeeded are included in the error.
+pub async fn run_all(commands: impl IntoIterator<Item = (impl Into<Cow<'static, str>>, Command)>, concurrency: usize, mode: FailureMode) -> Result<Vec<Output>> {
+    let (names, commands) = commands.into_iter().map(|(name, cmd)| (name.into(), cmd)).unzip::<_, _, Vec<_>, Vec<_>>();
+    let mut results = names.iter().map(|_| None).collect::<Vec<_>>();
+    let mut running = stream::iter(names.iter().cloned().zip(commands).enumerate())
+        .map(|(idx, (name, mut cmd))| async move {
+            // make sure running commands are killed if we stop early
+            cmd.kill_on_drop(true);
+            (idx, cmd.check(name).await)
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some((idx, result)) = running.next().await {
+        let failed = result.is_err();
+        results[idx] = Some(result);
+        if failed && mode == FailureMode::FailFast { break }
+    }
+    drop(running);
+    let mut succeeded = Vec::with_capacity(results.len());
+    let mut failures = Vec::default();
+    let mut skipped = Vec::default();
+    for (name, result) in names.into_iter().zip(results) {
+        match result {
+            Some(Ok(output)) => succeeded.push((name, output)),
+            Some(Err(e)) => failures.push(e),
+            None => skipped.push(name),
+        }
+    }
+    if failures.is_empty() {
+        Ok(succeeded.into_iter().map(|(_, output)| output).collect())
+    } else {
+        Err(Error::CommandsFailed { succeeded, failures, skipped })
+    }
+}
+
+/// Runs subprocesses on behalf of code that should be testable without the real programs being installed.
+///
+/// Use [`with_runner`] to make the command helpers use a runner, e.g. a [`ScriptedRunner`] in tests. Code that takes a `&dyn CommandRunner` can also use one directly.
+#[async_trait]
+pub trait CommandRunner: Send + Sync {
+    /// Runs the command to completion, capturing its stdout and stderr, like [`Command::output`].
+    async fn output(&self, cmd: &mut Command) -> io::Result<Output>;
+
+    /// Replaces the current process with the command, like [`AsyncCommandExt::exec`](crate::traits::AsyncCommandExt::exec).
+    async fn exec(&self, cmd: &mut Command, name: Cow<'static, str>) -> Result<Infallible>;
+
+    /// Like [`AsyncCommandOutputExt::check`](crate::traits::AsyncCommandOutputExt::check), but runs the command using this runner.
+    async fn check(&self, cmd: &mut Command, name: Cow<'static, str>) -> Result<Output> {
+        self.output(cmd).await.at_command(name.clone())?.check(name)
+    }
+
+    /// Like [`CommandRunner::check`], but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    async fn check_auto(&self, cmd: &mut Command) -> Result<Output> {
+        let name = CommandDisplay::new(cmd.as_std());
+        self.check(cmd, name.into()).await
+    }
+
+    /// Like [`CommandRunner::exec`], but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    async fn exec_auto(&self, cmd: &mut Command) -> Result<Infallible> {
+        let name = CommandDisplay::new(cmd.as_std());
+        self.exec(cmd, name.into()).await
+    }
+
+    /// Runs the stages of the given pipeline with each stage's stdout connected to the next stage's stdin, and returns each stage's name and output in pipeline order. Used by [`Pipeline::check`], which then checks the exit statuses.
+    async fn pipeline(&self, pipeline: Pipeline) -> Result<Vec<(Cow<'static, str>, Output)>> {
+        pipeline.run().await
+    }
+}
+
+tokio::task_local! {
+    static RUNNER: Arc<dyn CommandRunner>;
+}
+
+/// Runs the given future, making the command helpers use the given runner instead of running subprocesses directly.
+///
+/// While the future runs, the runner is used by [`AsyncCommandOutputExt::check`](crate::traits::AsyncCommandOutputExt::check) and [`AsyncCommandExt::exec`](crate::traits::AsyncCommandExt::exec) on [`Command`], the methods built on them such as `check_auto`, `exec_auto`, and `check_with_retry`, as well as [`Pipeline::check`], [`run_all`], [`SshCommand::check`], and `ChildGroup::check`. Tasks spawned by the future, the extension methods for [`std::process::Command`], and the other extension methods for [`Command`] such as `check_timeout`, `check_with_stdin`, `check_streaming`, and `check_pty` still run real subprocesses.
+pub async fn with_runner<F: Future>(runner: Arc<dyn CommandRunner>, future: F) -> F::Output {
+    RUNNER.scope(runner, future).await
+}
+
+/// Returns the runner set using [`with_runner`] for the current task, if any.
+pub(crate) fn current_runner() -> Option<Arc<dyn CommandRunner>> {
+    RUNNER.try_with(Arc::clone).ok()
+}
+
+/// A [`CommandRunner`] which actually runs the commands.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+#[async_trait]
+impl CommandRunner for SystemRunner {
+    async fn output(&self, cmd: &mut Command) -> io::Result<Output> {
+        cmd.output().await
+    }
+
+    async fn exec(&self, cmd: &mut Command, name: Cow<'static, str>) -> Result<Infallible> {
+        crate::traits::exec_command(cmd, name).await
+    }
+}
+
+/// A [`CommandRunner`] for tests which doesn't run anything, instead responding to commands according to a script.
+///
+/// Each command is matched against the rules in the order they were added, and the first matching rule's response is used. Commands which don't match any rule fail with [`io::ErrorKind::NotFound`], as if the program wasn't installed.
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    rules: Vec<ScriptedRule>,
+    calls: Mutex<Vec<ScriptedCall>>,
+}
+
+/// A command run by a [`ScriptedRunner`], as returned by [`ScriptedRunner::calls`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptedCall {
+    /// The program followed by its arguments.
+    pub command: Vec<OsString>,
+    /// Whether the command was run using [`CommandRunner::exec`], i.e. it would have replaced the current process.
+    pub exec: bool,
+}
+
+#[derive(Debug)]
+struct ScriptedRule {
+    program: OsString,
+    args: Option<Vec<OsString>>,
+    response: ScriptedResponse,
+}
+
+#[derive(Debug)]
+enum ScriptedResponse {
+    Output(Output),
+    Error(io::ErrorKind),
+}
+
+impl ScriptedRunner {
+    /// Creates a runner with no rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Responds to the given program with exactly the given arguments by returning the given output.
+    pub fn respond(mut self, program: impl AsRef<OsStr>, args: impl IntoIterator<Item = impl AsRef<OsStr>>, output: Output) -> Self {
+        self.rules.push(ScriptedRule {
+            program: program.as_ref().to_owned(),
+            args: Some(args.into_iter().map(|arg| arg.as_ref().to_owned()).collect()),
+            response: ScriptedResponse::Output(output),
+        });
+        self
+    }
+
+    /// Responds to the given program with any arguments by returning the given output.
+    pub fn respond_any_args(mut self, program: impl AsRef<OsStr>, output: Output) -> Self {
+        self.rules.push(ScriptedRule {
+            program: program.as_ref().to_owned(),
+            args: None,
+            response: ScriptedResponse::Output(output),
+        });
+        self
+    }
+
+    /// Responds to the given program with exactly the given arguments by failing to run it with an I/O error of the given kind.
+    pub fn fail(mut self, program: impl AsRef<OsStr>, args: impl IntoIterator<Item = impl AsRef<OsStr>>, kind: io::ErrorKind) -> Self {
+        self.rules.push(ScriptedRule {
+            program: program.as_ref().to_owned(),
+            args: Some(args.into_iter().map(|arg| arg.as_ref().to_owned()).collect()),
+            response: ScriptedResponse::Error(kind),
+        });
+        self
+    }
+
+    /// Returns each command run so far, in order.
+    pub fn calls(&self) -> Vec<ScriptedCall> {
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    fn respond_to(&self, cmd: &Command, exec: bool) -> io::Result<Output> {
+        let cmd = cmd.as_std();
+        let program = cmd.get_program();
+        let args = cmd.get_args().collect::<Vec<_>>();
+        self.calls.lock().unwrap_or_else(PoisonError::into_inner).push(ScriptedCall {
+            command: std::iter::once(program).chain(args.iter().copied()).map(OsStr::to_owned).collect(),
+            exec,
+        });
+        let rule = self.rules.iter()
+            .find(|rule| rule.program == program && rule.args.as_ref().is_none_or(|rule_args| *rule_args == args))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no scripted response for {}", CommandDisplay::new(cmd))))?;
+        match &rule.response {
+            ScriptedResponse::Output(output) => Ok(output.clone()),
+            &ScriptedResponse::Error(kind) => Err(kind.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl CommandRunner for ScriptedRunner {
+    async fn output(&self, cmd: &mut Command) -> io::Result<Output> {
+        self.respond_to(cmd, false)
+    }
+
+    /// Records the command as executed in [`ScriptedRunner::calls`]. Since the current process keeps running, this always returns an error: the error that [`CommandRunner::check`] would return if the scripted exit status isn't a success, and an [`io::ErrorKind::Unsupported`] error otherwise.
+    async fn exec(&self, cmd: &mut Command, name: Cow<'static, str>) -> Result<Infallible> {
+        self.respond_to(cmd, true).at_command(name.clone())?.check(name.clone())?;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the current process can't be replaced by a scripted runner")).at_command(name)
+    }
+
+    async fn pipeline(&self, pipeline: Pipeline) -> Result<Vec<(Cow<'static, str>, Output)>> {
+        pipeline.stages.into_iter()
+            .map(|(name, cmd)| Ok((name.clone(), self.respond_to(&cmd, false).at_command(name)?)))
+            .collect()
+    }
+}
+
+/// Creates an [`Output`] with the given exit code, stdout, and stderr, e.g. for use with [`ScriptedRunner`].
+pub fn fake_output(code: i32, stdout: impl Into<Vec<u8>>, stderr: impl Into<Vec<u8>>) -> Output {
+    Output {
+        status: exit_status(code),
+        stdout: stdout.into(),
+        stderr: stderr.into(),
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(code as u32)
+}
+
+/// Connection settings for running commands on a remote host using the `ssh` command-line client.
+///
+/// The remote command's arguments are quoted for a POSIX shell, so they arrive on the remote host exactly as given.
+#[derive(Debug, Clone)]
+pub struct Ssh {
+    program: PathBuf,
+    host: String,
+    user: Option<String>,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+    connect_timeout: Option<Duration>,
+    options: Vec<(String, String)>,
+}
+
+impl Ssh {
+    /// Creates connection settings for the given host, which may be a hostname, an IP address, or an alias from the SSH config.
+    ///
+    /// By default, `BatchMode` is enabled so `ssh` fails instead of prompting for a password.
+    pub fn new(host: impl ToString) -> Self {
+        Self {
+            program: PathBuf::from("ssh"),
+            host: host.to_string(),
+            user: None,
+            port: None,
+            identity_file: None,
+            connect_timeout: None,
+            options: vec![("BatchMode".to_owned(), "yes".to_owned())],
+        }
+    }
+
+    /// Uses the given program instead of `ssh`, e.g. a local stand-in script for tests. It's called with the same arguments as `ssh` would be.
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Logs in as the given user on the remote host.
+    pub fn user(mut self, user: impl ToString) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Connects to the given port instead of the default.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Authenticates using the given private key file.
+    pub fn identity_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity_file = Some(path.into());
+        self
+    }
+
+    /// Gives up if the connection can't be established within the given duration. Rounded up to whole seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets an arbitrary `ssh` option, as with `-o key=value`. See `ssh_config(5)`.
+    pub fn option(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.options.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Prepares running the given program on the remote host. Arguments can be added using [`SshCommand::arg`] and [`SshCommand::args`].
+    pub fn command(&self, program: impl ToString) -> SshCommand {
+        SshCommand {
+            ssh: self.clone(),
+            words: vec![program.to_string()],
+        }
+    }
+
+    fn destination(&self) -> String {
+        if let Some(ref user) = self.user {
+            format!("{user}@{}", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+}
+
+/// A command to be run on a remote host, created using [`Ssh::command`].
+#[derive(Debug, Clone)]
+pub struct SshCommand {
+    ssh: Ssh,
+    words: Vec<String>,
+}
+
+impl SshCommand {
+    /// Appends an argument to the remote command.
+    pub fn arg(&mut self, arg: impl ToString) -> &mut Self {
+        self.words.push(arg.to_string());
+        self
+    }
+
+    /// Appends arguments to the remote command.
+    pub fn args(&mut self, args: impl IntoIterator<Item = impl ToString>) -> &mut Self {
+        self.words.extend(args.into_iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// The remote command as it will be interpreted by the remote shell.
+    pub fn remote_command(&self) -> String {
+        self.words.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The name used for this command in errors, consisting of the destination and the remote command.
+    pub fn name(&self) -> String {
+        format!("{}: {}", self.ssh.destination(), self.remote_command())
+    }
+
+    /// Returns the local `ssh` command which runs this remote command.
+    pub fn to_command(&self) -> Command {
+        let mut cmd = Command::new(&self.ssh.program);
+        if let Some(port) = self.ssh.port {
+            cmd.arg("-p");
+            cmd.arg(port.to_string());
+        }
+        if let Some(ref identity_file) = self.ssh.identity_file {
+            cmd.arg("-i");
+            cmd.arg(identity_file);
+        }
+        if let Some(connect_timeout) = self.ssh.connect_timeout {
+            cmd.arg("-o");
+            cmd.arg(format!("ConnectTimeout={}", connect_timeout.as_secs() + u64::from(connect_timeout.subsec_nanos() > 0)));
+        }
+        for (key, value) in &self.ssh.options {
+            cmd.arg("-o");
+            cmd.arg(format!("{key}={value}"));
+        }
+        // end of options, so a destination starting with `-` can't be interpreted as one
+        cmd.arg("--");
+        cmd.arg(self.ssh.destination());
+        cmd.arg(self.remote_command());
+        cmd
+    }
+
+    /// Like [`AsyncCommandOutputExt::check`](crate::traits::AsyncCommandOutputExt::check), but runs the command on the remote host.
+    ///
+    /// Note that `ssh` itself exits with status 255 if it fails to connect.
+    pub async fn check(&self) -> Result<Output> {
+        self.to_command().check(self.name()).await
+    }
+
+    /// Like [`SshCommand::check`], but runs the `ssh` command using the given runner, e.g. a [`ScriptedRunner`] in tests.
+    pub async fn check_with(&self, runner: &dyn CommandRunner) -> Result<Output> {
+        runner.check(&mut self.to_command(), self.name().into()).await
+    }
+
+    /// Like [`AsyncCommandOutputExt::check_streaming`](crate::traits::AsyncCommandOutputExt::check_streaming), but runs the command on the remote host.
+    pub async fn check_streaming(&self, on_line: impl FnMut(OutputLine) + Send) -> Result<ExitStatus> {
+        self.to_command().check_streaming(self.name(), on_line).await
+    }
+}
+
+#[cfg(unix)]
+/// A set of subprocesses, each running in its own process group, which are killed together.
+///
+/// All process groups spawned using a `ChildGroup` are killed with `SIGKILL` when it's dropped, e.g. because the task owning it was cancelled. Use [`ChildGroup::shutdown`] to give them a chance to exit gracefully instead.
+#[derive(Debug, Default)]
+pub struct ChildGroup {
+    groups: Arc<Mutex<HashSet<Pid>>>,
+    signal_forwarder: Option<JoinHandle<()>>,
+}
+
+#[cfg(unix)]
+impl ChildGroup {
+    /// Creates an empty `ChildGroup`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forwards `SIGINT` and `SIGTERM` received by the current process to all process groups in this `ChildGroup`.
+    ///
+    /// Since the subprocesses run in their own process groups, they don't receive signals sent to the terminal's foreground process group (e.g. by pressing <kbd>Ctrl</kbd><kbd>C</kbd>) unless they're forwarded. Note that once this has been called, these signals no longer terminate the current process, even after this `ChildGroup` is dropped, since the signal handlers installed by tokio stay in place for the rest of the process's lifetime.
+    pub fn forward_signals(mut self) -> Result<Self> {
+        let mut sigint = signal(SignalKind::interrupt()).at_unknown()?;
+        let mut sigterm = signal(SignalKind::terminate()).at_unknown()?;
+        let groups = Arc::clone(&self.groups);
+        self.signal_forwarder = Some(tokio::spawn(async move {
+            loop {
+                let signal = tokio::select! {
+                    Some(()) = sigint.recv() => Signal::SIGINT,
+                    Some(()) = sigterm.recv() => Signal::SIGTERM,
+                    else => break,
+                };
+                signal_all(&groups, signal);
+            }
+        }));
+        Ok(self)
+    }
+
+    /// Spawns the given command in a new process group which is part of this `ChildGroup`.
+    ///
+    /// Wait for the returned child using [`ChildGroup::wait`] rather than [`Child::wait`], so its process group is removed from this `ChildGroup` once it exits and can't be confused with an unrelated process group which reuses its ID.
+    pub fn spawn(&self, cmd: &mut Command, name: impl Into<Cow<'static, str>>) -> Result<Child> {
+        let child = cmd.process_group(0).spawn().at_command(name)?;
+        let mut groups = lock(&self.groups);
+        // forget process groups that no longer exist so their IDs can't be confused with reused ones
+        groups.retain(|&pgid| killpg(pgid, None) != Err(Errno::ESRCH));
+        if let Some(pid) = child.id() {
+            groups.insert(Pid::from_raw(pid as i32));
+        }
+        Ok(child)
+    }
+
+    /// Like [`AsyncCommandOutputExt::check`](crate::traits::AsyncCommandOutputExt::check), but spawns the command as part of this `ChildGroup`.
+    ///
+    /// If a runner has been set using [`with_runner`], the command is run using it instead, outside of this `ChildGroup`.
+    pub async fn check(&self, cmd: &mut Command, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Output> {
+        if let Some(runner) = current_runner() {
+            return runner.check(cmd, name.into()).await
+        }
+        let child = self.spawn(cmd.stdout(Stdio::piped()).stderr(Stdio::piped()), name.clone())?;
+        let pid = child.id();
+        let output = child.wait_with_output().await.at_command(name.clone())?;
+        if let Some(pid) = pid {
+            self.forget(pid);
+        }
+        output.check(name)
+    }
+
+    /// Waits for a child spawned using [`ChildGroup::spawn`] to exit, then removes its process group from this `ChildGroup`.
+    pub async fn wait(&self, child: &mut Child, name: impl Into<Cow<'static, str>>) -> Result<ExitStatus> {
+        let pid = child.id();
+        let status = child.wait().await.at_command(name)?;
+        if let Some(pid) = pid {
+            self.forget(pid);
+        }
+        Ok(status)
+    }
+
+    /// Removes the process group led by the given child, which has been waited on, from this `ChildGroup`.
+    fn forget(&self, pid: u32) {
+        lock(&self.groups).remove(&Pid::from_raw(pid as i32));
+    }
+
+    /// Sends `SIGTERM` to all process groups in this `ChildGroup`, then waits for them to exit. Any processes still running after 5 seconds are killed with `SIGKILL`.
+    pub async fn shutdown(self) {
+        signal_all(&self.groups, Signal::SIGTERM);
+        let deadline = Instant::now() + KILL_GRACE_PERIOD;
+        while Instant::now() < deadline {
+            lock(&self.groups).retain(|&pgid| killpg(pgid, None) != Err(Errno::ESRCH));
+            if lock(&self.groups).is_empty() { break }
+            sleep(POLL_INTERVAL).await;
+        }
+        // remaining process groups are killed on drop
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ChildGroup {
+    fn drop(&mut self) {
+        if let Some(signal_forwarder) = self.signal_forwarder.take() {
+            signal_forwarder.abort();
+        }
+        signal_all(&self.groups, Signal::SIGKILL);
+    }
+}
+
+#[cfg(unix)]
+fn lock(groups: &Mutex<HashSet<Pid>>) -> std::sync::MutexGuard<'_, HashSet<Pid>> {
+    groups.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(unix)]
+fn signal_all(groups: &Mutex<HashSet<Pid>>, signal: Signal) {
+    for &pgid in &*lock(groups) {
+        let _ = killpg(pgid, signal); // the process group may have already exited
+    }
+}
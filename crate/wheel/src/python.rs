@@ -0,0 +1,112 @@
+//! Integration with Python via [`pyo3`].
+//!
+//! [`enum@Error`] and [`SharedError`] convert to a Python exception of a class matching the error: [`OSError`](https://docs.python.org/3/library/exceptions.html#OSError) subclasses for I/O errors, [`ValueError`](https://docs.python.org/3/library/exceptions.html#ValueError) for invalid data, and [`CommandError`] (a subclass of [`subprocess.CalledProcessError`](https://docs.python.org/3/library/subprocess.html#subprocess.CalledProcessError)) for failed subprocesses. Use [`register_exceptions`] to make `CommandError` available to Python code so it can be caught.
+
+use {
+    std::{
+        io,
+        process::ExitStatus,
+    },
+    pyo3::{
+        create_exception,
+        exceptions::{
+            PyBlockingIOError,
+            PyBrokenPipeError,
+            PyConnectionAbortedError,
+            PyConnectionRefusedError,
+            PyConnectionResetError,
+            PyException,
+            PyFileExistsError,
+            PyFileNotFoundError,
+            PyInterruptedError,
+            PyOSError,
+            PyPermissionError,
+            PyTimeoutError,
+            PyValueError,
+        },
+        import_exception,
+        prelude::*,
+    },
+    crate::{
+        Error,
+        IoErrorContext,
+        SharedError,
+    },
+};
+#[cfg(unix)] use std::os::unix::process::ExitStatusExt as _;
+
+import_exception!(subprocess, CalledProcessError);
+create_exception!(wheel, CommandError, CalledProcessError, "Raised when a subprocess exits with a non-success status. A subclass of `subprocess.CalledProcessError`, with `stdout` and `stderr` set to `None` if the output wasn't captured.");
+
+/// Adds the custom exception classes used when converting [`enum@Error`] to a Python exception (currently only [`CommandError`]) to the given module.
+pub fn register_exceptions(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("CommandError", module.py().get_type::<CommandError>())?;
+    Ok(())
+}
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        to_py_err(&e, e.to_string(), false)
+    }
+}
+
+impl From<SharedError> for PyErr {
+    fn from(e: SharedError) -> Self {
+        to_py_err(&e, e.to_string(), false)
+    }
+}
+
+/// Converts an error to the matching Python exception. If `has_context` is true, `message` includes context frames and is used in place of the usual message for I/O errors.
+fn to_py_err(e: &Error, message: String, has_context: bool) -> PyErr {
+    match e {
+        Error::Context { inner, .. } => to_py_err(inner, message, true),
+        Error::Shared(e) => to_py_err(e, message, has_context),
+        Error::Io { inner, context } => {
+            let strerror = if has_context { message } else { inner.to_string() };
+            os_error(inner, strerror, context)
+        }
+        #[cfg(feature = "serde_json")] Error::Json { .. } | Error::JsonPathToError { .. } | Error::CommandJson { .. } => PyValueError::new_err(message),
+        #[cfg(all(feature = "reqwest", feature = "serde_json"))] Error::ResponseJson { .. } | Error::ResponseJsonPathToError { .. } => PyValueError::new_err(message),
+        Error::CommandUtf8 { .. } => PyValueError::new_err(message),
+        // CalledProcessError(returncode, cmd, output, stderr)
+        Error::CommandExit { name, output } | Error::SudoPasswordRequired { name, output } => PyErr::new::<CommandError, _>((returncode(output.status), name.to_string(), Some(output.stdout.clone()), Some(output.stderr.clone()))),
+        // like a CalledProcessError for a command run with stderr=STDOUT
+        #[cfg(unix)] Error::CommandPtyExit { name, output } => PyErr::new::<CommandError, _>((returncode(output.status), name.to_string(), Some(output.output.clone()), None::<Vec<u8>>)),
+        Error::CommandExitStatus { name, status } => PyErr::new::<CommandError, _>((returncode(*status), name.to_string(), None::<Vec<u8>>, None::<Vec<u8>>)),
+        Error::CommandTimeout { .. } => PyTimeoutError::new_err(message),
+        _ => PyException::new_err(message),
+    }
+}
+
+/// Converts an exit status to a return code following the convention of Python's `subprocess` module, where a negative value indicates termination by a signal.
+fn returncode(status: ExitStatus) -> i32 {
+    #[cfg(unix)] if let Some(signal) = status.signal() {
+        return -signal
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Creates an instance of the `OSError` subclass matching the error kind, with the `filename` and `filename2` attributes set from the context.
+fn os_error(inner: &io::Error, strerror: String, context: &IoErrorContext) -> PyErr {
+    let (filename, filename2) = match context {
+        IoErrorContext::Path(path) => (Some(path.clone()), None),
+        IoErrorContext::DoublePath(src, dst) => (Some(src.clone()), Some(dst.clone())),
+        IoErrorContext::ConfigKey { file, .. } => (Some(file.clone()), None),
+        _ => (None, None),
+    };
+    // OSError(errno, strerror, filename, winerror, filename2)
+    let args = (inner.raw_os_error().unwrap_or_default(), strerror, filename, None::<i32>, filename2);
+    match inner.kind() {
+        io::ErrorKind::BrokenPipe => PyErr::new::<PyBrokenPipeError, _>(args),
+        io::ErrorKind::ConnectionRefused => PyErr::new::<PyConnectionRefusedError, _>(args),
+        io::ErrorKind::ConnectionAborted => PyErr::new::<PyConnectionAbortedError, _>(args),
+        io::ErrorKind::ConnectionReset => PyErr::new::<PyConnectionResetError, _>(args),
+        io::ErrorKind::Interrupted => PyErr::new::<PyInterruptedError, _>(args),
+        io::ErrorKind::NotFound => PyErr::new::<PyFileNotFoundError, _>(args),
+        io::ErrorKind::PermissionDenied => PyErr::new::<PyPermissionError, _>(args),
+        io::ErrorKind::AlreadyExists => PyErr::new::<PyFileExistsError, _>(args),
+        io::ErrorKind::WouldBlock => PyErr::new::<PyBlockingIOError, _>(args),
+        io::ErrorKind::TimedOut => PyErr::new::<PyTimeoutError, _>(args),
+        _ => PyErr::new::<PyOSError, _>(args),
+    }
+}
@@ -0,0 +1,216 @@
+//! Retrying operations which fail with transient errors, as decided by [`IsNetworkError`].
+//!
+//! The same [`RetryPolicy`] is used for retrying subprocesses using `check_with_retry`, see e.g. [`SyncCommandExt::check_with_retry`](crate::traits::SyncCommandExt::check_with_retry).
+
+use {
+    std::{
+        collections::hash_map::RandomState,
+        fmt,
+        hash::BuildHasher as _,
+        sync::Arc,
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    crate::{
+        Error,
+        traits::IsNetworkError,
+    },
+};
+#[cfg(feature = "tokio")] use tokio::time::sleep;
+
+/// Decides how often and how long [`retry`], [`retry_sync`], and `check_with_retry` keep retrying an operation.
+///
+/// An operation is retried only if its error is retryable, i.e. a network error according to [`IsNetworkError::is_network_error`] or an error made retryable using [`RetryPolicy::retry_if`] or one of the other builder methods.
+pub struct RetryPolicy<E = Error> {
+    max_attempts: usize,
+    max_total_time: Option<Duration>,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retry_after: Option<RetryAfterHook<E>>,
+    on_retry: Vec<OnRetryHook<E>>,
+    predicates: Vec<RetryPredicate<E>>,
+}
+
+type RetryPredicate<E> = Arc<dyn Fn(&E) -> bool + Send + Sync>;
+type RetryAfterHook<E> = Arc<dyn Fn(&E) -> Option<Duration> + Send + Sync>;
+type OnRetryHook<E> = Arc<dyn Fn(&E, usize, Duration) + Send + Sync>;
+
+impl<E> RetryPolicy<E> {
+    /// Creates a policy which runs the operation at most the given number of times in total, waiting 1 second before the first retry and doubling the delay after each attempt, up to 1 minute.
+    pub fn new(max_attempts: usize) -> Self {
+        Self {
+            max_total_time: None,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.0,
+            retry_after: None,
+            on_retry: Vec::default(),
+            predicates: Vec::default(),
+            max_attempts,
+        }
+    }
+
+    /// Stops retrying if the next attempt would start more than the given duration after the first one.
+    pub fn max_total_time(mut self, time: Duration) -> Self {
+        self.max_total_time = Some(time);
+        self
+    }
+
+    /// Sets the delay before the first retry. The delay is doubled after each attempt.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the maximum delay between attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Randomly shortens each delay by up to the given fraction (between 0 and 1) of its length, so that clients which failed at the same time don't all retry at the same time.
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Also retries if the given function returns `true` for the error.
+    pub fn retry_if(mut self, predicate: impl Fn(&E) -> bool + Send + Sync + 'static) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Uses the given function to determine the delay before retrying, e.g. from a `Retry-After` HTTP header.
+    ///
+    /// If the function returns `None`, the exponential backoff delay is used. Delays returned by the function are not limited by [`RetryPolicy::max_delay`] or affected by [`RetryPolicy::jitter`].
+    pub fn retry_after(mut self, hook: impl Fn(&E) -> Option<Duration> + Send + Sync + 'static) -> Self {
+        self.retry_after = Some(Arc::new(hook));
+        self
+    }
+
+    /// Calls the given function before each retry with the error, the number of failed attempts so far, and the delay before the next attempt.
+    pub fn on_retry(mut self, hook: impl Fn(&E, usize, Duration) + Send + Sync + 'static) -> Self {
+        self.on_retry.push(Arc::new(hook));
+        self
+    }
+
+    /// Prints a message to stderr before each retry, prefixed with the given name.
+    pub fn log(self, name: impl fmt::Display + Send + Sync + 'static) -> Self
+    where E: fmt::Display {
+        let max_attempts = self.max_attempts;
+        self.on_retry(move |e, attempts, delay| eprintln!("{name}: attempt {attempts} of {max_attempts} failed: {e}, retrying in {delay:.1?}"))
+    }
+}
+
+impl RetryPolicy {
+    /// Also retries if a subprocess exits with the given exit code.
+    pub fn retry_on_exit_code(self, code: i32) -> Self {
+        self.retry_if(move |e| e.exit_code() == Some(code))
+    }
+
+    /// Also retries if a subprocess exits with a non-success status and its stderr contains the given text.
+    pub fn retry_on_stderr(self, pattern: impl ToString) -> Self {
+        let pattern = pattern.to_string();
+        self.retry_if(move |e| e.stderr_lossy().is_some_and(|stderr| stderr.contains(&pattern)))
+    }
+}
+
+impl<E: IsNetworkError> RetryPolicy<E> {
+    /// Returns whether this policy considers the given error retryable.
+    pub fn is_retryable(&self, e: &E) -> bool {
+        e.is_network_error() || self.predicates.iter().any(|predicate| predicate(e))
+    }
+
+    /// Decides whether to retry after the given number of failed attempts, the last of which failed with the given error. If so, calls the [`RetryPolicy::on_retry`] hooks and returns the delay before the next attempt.
+    pub(crate) fn next_delay(&self, start: Instant, attempts: usize, e: &E) -> Option<Duration> {
+        if attempts >= self.max_attempts || !self.is_retryable(e) { return None }
+        let delay = if let Some(delay) = self.retry_after.as_ref().and_then(|retry_after| retry_after(e)) {
+            delay
+        } else {
+            let factor = 2u32.saturating_pow(attempts.saturating_sub(1).try_into().unwrap_or(u32::MAX));
+            let delay = self.initial_delay.saturating_mul(factor).min(self.max_delay);
+            if self.jitter > 0.0 {
+                // use the randomly seeded std hasher to avoid a dependency on rand
+                let random = (RandomState::new().hash_one(attempts) >> 11) as f64 / (1u64 << 53) as f64;
+                delay.mul_f64(1.0 - self.jitter * random)
+            } else {
+                delay
+            }
+        };
+        if self.max_total_time.is_some_and(|max_total_time| start.elapsed().saturating_add(delay) > max_total_time) { return None }
+        for hook in &self.on_retry {
+            hook(e, attempts, delay);
+        }
+        Some(delay)
+    }
+}
+
+impl<E> Clone for RetryPolicy<E> {
+    fn clone(&self) -> Self {
+        Self {
+            max_attempts: self.max_attempts,
+            max_total_time: self.max_total_time,
+            initial_delay: self.initial_delay,
+            max_delay: self.max_delay,
+            jitter: self.jitter,
+            retry_after: self.retry_after.clone(),
+            on_retry: self.on_retry.clone(),
+            predicates: self.predicates.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for RetryPolicy<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("max_total_time", &self.max_total_time)
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Runs the given operation until it succeeds, fails with an error that's not a network error, or the policy gives up. Returns the result of the last attempt.
+pub async fn retry<T, E: IsNetworkError, Fut: Future<Output = Result<T, E>>>(policy: &RetryPolicy<E>, mut operation: impl FnMut() -> Fut) -> Result<T, E> {
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        match operation().await {
+            Ok(x) => break Ok(x),
+            Err(e) => {
+                attempts += 1;
+                if let Some(delay) = policy.next_delay(start, attempts, &e) {
+                    sleep(delay).await;
+                } else {
+                    break Err(e)
+                }
+            }
+        }
+    }
+}
+
+/// Like [`retry`], but for blocking operations. Sleeps the current thread between attempts.
+pub fn retry_sync<T, E: IsNetworkError>(policy: &RetryPolicy<E>, mut operation: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        match operation() {
+            Ok(x) => break Ok(x),
+            Err(e) => {
+                attempts += 1;
+                if let Some(delay) = policy.next_delay(start, attempts, &e) {
+                    thread::sleep(delay);
+                } else {
+                    break Err(e)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Exit codes following the conventions of BSD's `sysexits.h`, as returned by [`ToExitCode`](crate::traits::ToExitCode).
+
+/// The command was used incorrectly, e.g. with the wrong number of arguments.
+pub const USAGE: i32 = 64;
+/// The input data was incorrect in some way, e.g. invalid JSON.
+pub const DATAERR: i32 = 65;
+/// An input file did not exist or was not readable.
+pub const NOINPUT: i32 = 66;
+/// A service or support program is unavailable, e.g. a subprocess could not be started.
+pub const UNAVAILABLE: i32 = 69;
+/// An internal software error has been detected, e.g. a subprocess failed.
+pub const SOFTWARE: i32 = 70;
+/// An error occurred while doing I/O.
+pub const IOERR: i32 = 74;
+/// A temporary failure, e.g. a network error, indicating that retrying might succeed.
+pub const TEMPFAIL: i32 = 75;
+/// The user did not have sufficient permission to perform the operation.
+pub const NOPERM: i32 = 77;
+/// Something was found in an unconfigured or misconfigured state.
+pub const CONFIG: i32 = 78;
@@ -4,14 +4,57 @@ use {
     std::{
         borrow::Cow,
         convert::Infallible,
-        io,
-        path::Path,
+        env,
+        ffi::{
+            OsStr,
+            OsString,
+        },
+        io::{
+            self,
+            prelude::*,
+        },
+        mem,
+        net::SocketAddr,
+        path::{
+            Path,
+            PathBuf,
+        },
+        process::Stdio,
+        sync::{
+            Arc,
+            Mutex,
+            PoisonError,
+        },
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
     },
     async_trait::async_trait,
     crate::{
+        CommandDisplay,
         Error,
         IoErrorContext,
         Result,
+        SharedError,
+        retry::RetryPolicy,
+    },
+};
+#[cfg(unix)] use {
+    std::fs::File,
+    nix::{
+        errno::Errno,
+        pty::{
+            OpenptyResult,
+            Winsize,
+            openpty,
+        },
+        sys::signal::{
+            Signal,
+            kill,
+        },
+        unistd::Pid,
     },
 };
 #[cfg(windows)] use std::os::windows::process::CommandExt as _;
@@ -23,12 +66,18 @@ use {
     futures::stream::TryStreamExt as _,
     tokio_util::io::StreamReader,
 };
-#[cfg(all(feature = "reqwest", feature = "serde_json"))] use serde::de::DeserializeOwned;
-#[cfg(all(feature = "chrono", feature = "reqwest"))] use {
-    std::time::Duration,
-    tokio::time::sleep,
+#[cfg(feature = "serde_json")] use serde::de::DeserializeOwned;
+#[cfg(feature = "tokio")] use tokio::io::{
+    AsyncBufReadExt as _,
+    AsyncReadExt as _,
+    AsyncWriteExt as _,
+    BufReader,
+};
+#[cfg(feature = "tokio")] use tokio::time::sleep;
+pub use wheel_derive::{
+    IsNetworkError,
+    ToExitCode,
 };
-pub use wheel_derive::IsNetworkError;
 
 /// A convenience method for working with infallible results
 pub trait ResultNeverExt {
@@ -83,6 +132,15 @@ pub trait IoResultExt {
     fn at2(self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<Self::Ok>;
     /// Converts the [`Err`] variant of `self` by annotating it with the given command name.
     fn at_command(self, name: impl Into<Cow<'static, str>>) -> Result<Self::Ok>;
+    #[cfg(feature = "url")]
+    /// Converts the [`Err`] variant of `self` by annotating it with the given URL.
+    fn at_url(self, url: &url::Url) -> Result<Self::Ok>;
+    /// Converts the [`Err`] variant of `self` by annotating it with the given environment variable name.
+    fn at_env(self, name: impl ToString) -> Result<Self::Ok>;
+    /// Converts the [`Err`] variant of `self` by annotating it with the given configuration file and key.
+    fn at_config_key(self, file: impl AsRef<Path>, key: impl ToString) -> Result<Self::Ok>;
+    /// Converts the [`Err`] variant of `self` by annotating it with the given socket address.
+    fn at_addr(self, addr: SocketAddr) -> Result<Self::Ok>;
     /// Converts an [`Err`] with [`io::ErrorKind::AlreadyExists`] to `Ok(default())`.
     fn exist_ok(self) -> Self where Self::Ok: Default;
     /// Converts an [`Err`] with [`io::ErrorKind::NotFound`] to `Ok(default())`.
@@ -108,6 +166,23 @@ impl<T> IoResultExt for io::Result<T> {
         self.map_err(|inner| Error::Io { inner, context: IoErrorContext::Command(name.into()) })
     }
 
+    #[cfg(feature = "url")]
+    fn at_url(self, url: &url::Url) -> Result<T> {
+        self.map_err(|inner| Error::Io { inner, context: IoErrorContext::Url(url.clone()) })
+    }
+
+    fn at_env(self, name: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::Io { inner, context: IoErrorContext::EnvVar(name.to_string()) })
+    }
+
+    fn at_config_key(self, file: impl AsRef<Path>, key: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::Io { inner, context: IoErrorContext::ConfigKey { file: file.as_ref().to_owned(), key: key.to_string() } })
+    }
+
+    fn at_addr(self, addr: SocketAddr) -> Result<T> {
+        self.map_err(|inner| Error::Io { inner, context: IoErrorContext::Socket(addr) })
+    }
+
     fn exist_ok(self) -> Self where T: Default {
         match self {
             Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(T::default()),
@@ -154,6 +229,35 @@ impl<T> IoResultExt for Result<T> {
         }
     }
 
+    #[cfg(feature = "url")]
+    fn at_url(self, url: &url::Url) -> Result<T> {
+        match self {
+            Err(Error::Io { inner, .. }) => Err(Error::Io { inner, context: IoErrorContext::Url(url.clone()) }),
+            _ => self,
+        }
+    }
+
+    fn at_env(self, name: impl ToString) -> Result<T> {
+        match self {
+            Err(Error::Io { inner, .. }) => Err(Error::Io { inner, context: IoErrorContext::EnvVar(name.to_string()) }),
+            _ => self,
+        }
+    }
+
+    fn at_config_key(self, file: impl AsRef<Path>, key: impl ToString) -> Result<T> {
+        match self {
+            Err(Error::Io { inner, .. }) => Err(Error::Io { inner, context: IoErrorContext::ConfigKey { file: file.as_ref().to_owned(), key: key.to_string() } }),
+            _ => self,
+        }
+    }
+
+    fn at_addr(self, addr: SocketAddr) -> Result<T> {
+        match self {
+            Err(Error::Io { inner, .. }) => Err(Error::Io { inner, context: IoErrorContext::Socket(addr) }),
+            _ => self,
+        }
+    }
+
     fn exist_ok(self) -> Self where T: Default {
         match self {
             Err(Error::Io { inner, .. }) if inner.kind() == io::ErrorKind::AlreadyExists => Ok(T::default()),
@@ -189,6 +293,23 @@ impl<T> IoResultExt for serde_json::Result<T> {
         self.map_err(|inner| Error::Json { inner, context: IoErrorContext::Command(name.into()) })
     }
 
+    #[cfg(feature = "url")]
+    fn at_url(self, url: &url::Url) -> Result<T> {
+        self.map_err(|inner| Error::Json { inner, context: IoErrorContext::Url(url.clone()) })
+    }
+
+    fn at_env(self, name: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::Json { inner, context: IoErrorContext::EnvVar(name.to_string()) })
+    }
+
+    fn at_config_key(self, file: impl AsRef<Path>, key: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::Json { inner, context: IoErrorContext::ConfigKey { file: file.as_ref().to_owned(), key: key.to_string() } })
+    }
+
+    fn at_addr(self, addr: SocketAddr) -> Result<T> {
+        self.map_err(|inner| Error::Json { inner, context: IoErrorContext::Socket(addr) })
+    }
+
     fn exist_ok(self) -> Self where T: Default { self }
     fn missing_ok(self) -> Self where T: Default { self }
 }
@@ -213,10 +334,64 @@ impl<T> IoResultExt for serde_json_path_to_error::Result<T> {
         self.map_err(|inner| Error::JsonPathToError { inner, context: IoErrorContext::Command(name.into()) })
     }
 
+    #[cfg(feature = "url")]
+    fn at_url(self, url: &url::Url) -> Result<T> {
+        self.map_err(|inner| Error::JsonPathToError { inner, context: IoErrorContext::Url(url.clone()) })
+    }
+
+    fn at_env(self, name: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::JsonPathToError { inner, context: IoErrorContext::EnvVar(name.to_string()) })
+    }
+
+    fn at_config_key(self, file: impl AsRef<Path>, key: impl ToString) -> Result<T> {
+        self.map_err(|inner| Error::JsonPathToError { inner, context: IoErrorContext::ConfigKey { file: file.as_ref().to_owned(), key: key.to_string() } })
+    }
+
+    fn at_addr(self, addr: SocketAddr) -> Result<T> {
+        self.map_err(|inner| Error::JsonPathToError { inner, context: IoErrorContext::Socket(addr) })
+    }
+
     fn exist_ok(self) -> Self where T: Default { self }
     fn missing_ok(self) -> Self where T: Default { self }
 }
 
+/// Allows annotating the [`Err`] variant of a [`Result`] or [`io::Result`] with a human-readable description of what was being done when the error occurred.
+///
+/// Annotations accumulate in [`Error::Context`] and are displayed as a “caused by” chain, outermost first.
+pub trait ResultContextExt {
+    /// The [`Ok`] variant of the returned [`Result`] type.
+    type Ok;
+
+    /// Annotates the [`Err`] variant of `self` with the given description, e.g. `"while loading user profile"`.
+    fn context(self, context: impl ToString) -> Result<Self::Ok>;
+    /// Like `context`, but only computes the description if `self` is an [`Err`].
+    fn with_context<C: ToString>(self, f: impl FnOnce() -> C) -> Result<Self::Ok>;
+}
+
+impl<T> ResultContextExt for Result<T> {
+    type Ok = T;
+
+    fn context(self, context: impl ToString) -> Result<T> {
+        self.map_err(|e| e.context(context.to_string()))
+    }
+
+    fn with_context<C: ToString>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.context(f().to_string()))
+    }
+}
+
+impl<T> ResultContextExt for io::Result<T> {
+    type Ok = T;
+
+    fn context(self, context: impl ToString) -> Result<T> {
+        self.at_unknown().context(context)
+    }
+
+    fn with_context<C: ToString>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.at_unknown().with_context(f)
+    }
+}
+
 #[cfg_attr(feature = "tokio", doc = "Extension methods for [`tokio::process::Command`] and [`std::process::Command`]")]
 #[cfg_attr(not(feature = "tokio"), doc = "Extension methods for [`std::process::Command`]")]
 pub trait CommandExt {
@@ -228,6 +403,53 @@ pub trait CommandExt {
         #[cfg(not(debug_assertions))] self.create_no_window();
         self
     }
+
+    /// Returns a command which runs this command as root using `sudo`. See [`CommandExt::as_user`].
+    fn sudo(&self) -> Self where Self: Sized;
+
+    /// Returns a command which runs this command as the given user using `sudo`.
+    ///
+    /// `sudo` is run with `-n`, so it fails instead of prompting for a password, which is reported as [`Error::SudoPasswordRequired`] by the `check` methods. To recognize this regardless of the system language, `sudo` itself runs with `LC_ALL=C`, and the command is run using `env` to restore the original value. Environment variables set explicitly on this command are passed through using `--preserve-env`, which requires the `sudoers` policy to allow it; other environment variables are subject to `sudo`'s usual filtering unless allowed using [`SudoOptions::preserve_env`]. The working directory is kept, but stdio configuration is not.
+    fn as_user(&self, user: impl AsRef<OsStr>) -> Self where Self: Sized;
+
+    /// Returns a command which runs this command using `sudo` as configured by the given options. See [`CommandExt::as_user`].
+    fn sudo_with_options(&self, options: &SudoOptions) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with its virtual memory limited to the given number of bytes. Allocations beyond the limit fail.
+    ///
+    /// Like the other resource limits, this is applied by wrapping the command in util-linux's `prlimit`, which must be installed. The limit is set immediately before the command is executed, so it doesn't affect the current process. Explicitly set environment variables and the working directory are kept, but stdio configuration is not.
+    fn limit_memory(&self, bytes: u64) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with its CPU time limited to the given number of seconds. If the limit is exceeded, the command is killed by `SIGXCPU`, which is mentioned in the resulting [`Error::CommandExit`].
+    ///
+    /// See [`CommandExt::limit_memory`] for how the limit is applied.
+    fn limit_cpu_time(&self, seconds: u64) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with the size of files it writes limited to the given number of bytes. If the limit is exceeded, the command is killed by `SIGXFSZ`, which is mentioned in the resulting [`Error::CommandExit`].
+    ///
+    /// See [`CommandExt::limit_memory`] for how the limit is applied.
+    fn limit_file_size(&self, bytes: u64) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with the number of file descriptors it can open limited to the given number. Opening more fails.
+    ///
+    /// See [`CommandExt::limit_memory`] for how the limit is applied.
+    fn limit_open_files(&self, count: u64) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with the `no_new_privs` flag set, so neither it nor its descendants can gain privileges, e.g. via setuid binaries like `sudo`.
+    ///
+    /// This is applied by wrapping the command in util-linux's `setpriv`, which must be installed. Explicitly set environment variables and the working directory are kept, but stdio configuration is not.
+    fn no_new_privileges(&self) -> Self where Self: Sized;
+
+    #[cfg(target_os = "linux")]
+    /// Returns a command which runs this command with the given directory as its root directory and working directory, so it can't access files outside of it.
+    ///
+    /// The program path and any absolute paths in the arguments are resolved inside the jail, so the directory must contain the program and everything it needs to run, e.g. shared libraries. This is applied by wrapping the command in util-linux's `unshare`, which must be installed. It runs the command in a new user namespace in which the current user is mapped to root, so it works without privileges if the system allows unprivileged user namespaces. Explicitly set environment variables are kept, but stdio configuration is not. If `root` is a relative path, it's resolved relative to this command's working directory.
+    fn jail(&self, root: impl AsRef<Path>) -> Self where Self: Sized;
 }
 
 #[cfg(feature = "tokio")]
@@ -236,6 +458,49 @@ impl CommandExt for tokio::process::Command {
         #[cfg(windows)] { self.creation_flags(0x0800_0000) }
         #[cfg(not(windows))] { self }
     }
+
+    fn sudo(&self) -> tokio::process::Command {
+        sudo_command(self.as_std(), &SudoOptions::new()).into()
+    }
+
+    fn as_user(&self, user: impl AsRef<OsStr>) -> tokio::process::Command {
+        sudo_command(self.as_std(), &SudoOptions::new().user(user)).into()
+    }
+
+    fn sudo_with_options(&self, options: &SudoOptions) -> tokio::process::Command {
+        sudo_command(self.as_std(), options).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_memory(&self, bytes: u64) -> tokio::process::Command {
+        prlimit_command(self.as_std(), "as", bytes, bytes).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_cpu_time(&self, seconds: u64) -> tokio::process::Command {
+        // a higher hard limit makes the kernel send SIGXCPU rather than SIGKILL
+        prlimit_command(self.as_std(), "cpu", seconds, seconds.saturating_add(1)).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_file_size(&self, bytes: u64) -> tokio::process::Command {
+        prlimit_command(self.as_std(), "fsize", bytes, bytes).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_open_files(&self, count: u64) -> tokio::process::Command {
+        prlimit_command(self.as_std(), "nofile", count, count).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn no_new_privileges(&self) -> tokio::process::Command {
+        wrap_command(self.as_std(), "setpriv", ["--no-new-privs"]).into()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn jail(&self, root: impl AsRef<Path>) -> tokio::process::Command {
+        jail_command(self.as_std(), root.as_ref()).into()
+    }
 }
 
 impl CommandExt for std::process::Command {
@@ -243,6 +508,178 @@ impl CommandExt for std::process::Command {
         #[cfg(windows)] { self.creation_flags(0x0800_0000) }
         #[cfg(not(windows))] { self }
     }
+
+    fn sudo(&self) -> std::process::Command {
+        sudo_command(self, &SudoOptions::new())
+    }
+
+    fn as_user(&self, user: impl AsRef<OsStr>) -> std::process::Command {
+        sudo_command(self, &SudoOptions::new().user(user))
+    }
+
+    fn sudo_with_options(&self, options: &SudoOptions) -> std::process::Command {
+        sudo_command(self, options)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_memory(&self, bytes: u64) -> std::process::Command {
+        prlimit_command(self, "as", bytes, bytes)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_cpu_time(&self, seconds: u64) -> std::process::Command {
+        // a higher hard limit makes the kernel send SIGXCPU rather than SIGKILL
+        prlimit_command(self, "cpu", seconds, seconds.saturating_add(1))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_file_size(&self, bytes: u64) -> std::process::Command {
+        prlimit_command(self, "fsize", bytes, bytes)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn limit_open_files(&self, count: u64) -> std::process::Command {
+        prlimit_command(self, "nofile", count, count)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn no_new_privileges(&self) -> std::process::Command {
+        wrap_command(self, "setpriv", ["--no-new-privs"])
+    }
+
+    #[cfg(target_os = "linux")]
+    fn jail(&self, root: impl AsRef<Path>) -> std::process::Command {
+        jail_command(self, root.as_ref())
+    }
+}
+
+/// Options for [`CommandExt::sudo_with_options`].
+#[derive(Debug, Default, Clone)]
+pub struct SudoOptions {
+    user: Option<OsString>,
+    preserve_env: Vec<OsString>,
+}
+
+impl SudoOptions {
+    /// Creates options which run the command as root, passing through only the environment variables set explicitly on the command.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the command as the given user instead of root.
+    pub fn user(mut self, user: impl AsRef<OsStr>) -> Self {
+        self.user = Some(user.as_ref().to_owned());
+        self
+    }
+
+    /// Passes the given environment variable from the current process to the command, e.g. `HOME` or `SSH_AUTH_SOCK`. Like explicitly set environment variables, it's passed using `--preserve-env`, which requires the `sudoers` policy to allow it.
+    pub fn preserve_env(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.preserve_env.push(key.as_ref().to_owned());
+        self
+    }
+}
+
+/// The beginning of the message `sudo -n` prints if it would have to prompt for a password, in the `C` locale.
+const SUDO_PASSWORD_REQUIRED: &[u8] = b"sudo: a password is required";
+
+fn sudo_command(cmd: &std::process::Command, options: &SudoOptions) -> std::process::Command {
+    let mut args = vec![OsString::from("-n")];
+    if let Some(ref user) = options.user {
+        args.push(OsString::from("-u"));
+        args.push(user.clone());
+    }
+    let mut preserved = cmd.get_envs().filter(|(_, value)| value.is_some()).map(|(key, _)| key).collect::<Vec<_>>();
+    for key in &options.preserve_env {
+        if !preserved.contains(&&**key) {
+            preserved.push(key);
+        }
+    }
+    if !preserved.is_empty() {
+        let mut preserve_env = OsString::from("--preserve-env=");
+        preserve_env.push(preserved.join(OsStr::new(",")));
+        args.push(preserve_env);
+    }
+    // sudo's messages are localized, so LC_ALL=C is set for sudo itself to detect SUDO_PASSWORD_REQUIRED.
+    // Since sudo usually passes LC_ALL on, the command is run via env to restore the original value.
+    args.push(OsString::from("--"));
+    args.push(OsString::from("env"));
+    let lc_all = cmd.get_envs().find(|&(key, _)| key == "LC_ALL").map_or_else(|| env::var_os("LC_ALL"), |(_, value)| value.map(OsStr::to_owned));
+    if let Some(lc_all) = lc_all {
+        let mut assignment = OsString::from("LC_ALL=");
+        assignment.push(lc_all);
+        args.push(assignment);
+    } else {
+        args.push(OsString::from("-u"));
+        args.push(OsString::from("LC_ALL"));
+        args.push(OsString::from("--"));
+    }
+    let mut sudo = std::process::Command::new("sudo");
+    sudo.args(args);
+    sudo.arg(cmd.get_program());
+    sudo.args(cmd.get_args());
+    copy_env_and_current_dir(cmd, &mut sudo);
+    sudo.env("LC_ALL", "C");
+    sudo
+}
+
+/// Returns whether the given command was created by [`sudo_command`].
+fn is_sudo_command(cmd: &std::process::Command) -> bool {
+    cmd.get_program() == "sudo"
+    && cmd.get_args().next().is_some_and(|arg| arg == "-n")
+    && cmd.get_envs().any(|(key, value)| key == "LC_ALL" && value.is_some_and(|value| value == "C"))
+}
+
+/// If the given command was created using [`CommandExt::sudo`] or [`CommandExt::as_user`], returns a function which turns an error caused by `sudo` requiring a password into [`Error::SudoPasswordRequired`]. Otherwise, the function returns errors unchanged.
+fn detect_sudo_password(cmd: &std::process::Command) -> impl FnOnce(Error) -> Error + Send + use<> {
+    let is_sudo = is_sudo_command(cmd);
+    move |e| match e {
+        Error::CommandExit { name, output } if is_sudo && output.stderr.starts_with(SUDO_PASSWORD_REQUIRED) => Error::SudoPasswordRequired { name, output },
+        // with check_pty, sudo's stderr is the terminal, and sudo fails before the command can write anything to it
+        #[cfg(unix)] Error::CommandPtyExit { name, output } if is_sudo && output.stripped().starts_with(SUDO_PASSWORD_REQUIRED) => Error::SudoPasswordRequired {
+            name,
+            output: std::process::Output { status: output.status, stdout: Vec::default(), stderr: output.stripped() },
+        },
+        e => e,
+    }
+}
+
+/// Returns a command which runs `program` with the given arguments, followed by `--` and the given command's program and arguments.
+///
+/// Explicitly set environment variables and the working directory are copied to the new command.
+fn wrap_command(cmd: &std::process::Command, program: &str, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> std::process::Command {
+    let mut wrapper = std::process::Command::new(program);
+    wrapper.args(args);
+    wrapper.arg("--");
+    wrapper.arg(cmd.get_program());
+    wrapper.args(cmd.get_args());
+    copy_env_and_current_dir(cmd, &mut wrapper);
+    wrapper
+}
+
+/// Copies explicitly set environment variables and the working directory from `cmd` to `wrapper`.
+fn copy_env_and_current_dir(cmd: &std::process::Command, wrapper: &mut std::process::Command) {
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            wrapper.env(key, value);
+        } else {
+            wrapper.env_remove(key);
+        }
+    }
+    if let Some(current_dir) = cmd.get_current_dir() {
+        wrapper.current_dir(current_dir);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn jail_command(cmd: &std::process::Command, root: &Path) -> std::process::Command {
+    let mut root_arg = OsString::from("--root=");
+    root_arg.push(root);
+    wrap_command(cmd, "unshare", [root_arg.as_os_str(), OsStr::new("--wd=/"), OsStr::new("--user"), OsStr::new("--map-root-user")])
+}
+
+#[cfg(target_os = "linux")]
+fn prlimit_command(cmd: &std::process::Command, resource: &str, soft: u64, hard: u64) -> std::process::Command {
+    wrap_command(cmd, "prlimit", [format!("--{resource}={soft}:{hard}")])
 }
 
 #[cfg(feature = "tokio")]
@@ -251,8 +688,28 @@ impl CommandExt for std::process::Command {
 pub trait AsyncCommandExt {
     /// Runs the command, then exits the current process, forwarding the command's exit status.
     ///
-    /// Uses the native `exec` on Unix, and an approximation using `check` on other platforms.
+    /// Uses the native `exec` on Unix. On other platforms, the command is run as a subprocess with inherited stdio, and the current process exits with its exit code once it exits. Ctrl+C is left for the subprocess to handle.
     async fn exec(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible>;
+
+    /// Like `exec`, but first applies the given options to the command.
+    async fn exec_with_options(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible>;
+
+    /// Like `exec`, but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    async fn exec_auto(self) -> Result<Infallible>;
+
+    /// Like [`AsyncCommandOutputExt::check`], but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    async fn check_auto(self) -> Result<std::process::Output>;
+
+    /// Like [`AsyncCommandOutputExt::check`], but runs the command again if it fails with an error that the given policy considers retryable (see [`RetryPolicy::is_retryable`]).
+    ///
+    /// If the command is run more than once without succeeding, the errors from all attempts are returned as [`Error::CommandRetries`].
+    async fn check_with_retry(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, policy: &RetryPolicy) -> Result<std::process::Output>;
+
+    #[cfg(unix)]
+    /// Like [`AsyncCommandOutputExt::check`], but runs the command with its stdout and stderr attached to a pseudo-terminal, so it behaves as if run interactively, e.g. by using colours and progress bars.
+    ///
+    /// Since both streams go to the same terminal, they are captured together. If the command doesn't exit successfully, the combined output is returned as part of [`Error::CommandPtyExit`]. Stdin is used as configured. When called on a `&mut` command, its stdout and stderr are left configured as piped, as with the other `check` methods.
+    async fn check_pty(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<PtyOutput>;
 }
 
 #[cfg(feature = "tokio")]
@@ -260,49 +717,327 @@ impl AsyncCommandExt for tokio::process::Command {
     async fn exec(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible> {
         (&mut self).exec(name).await
     }
+
+    async fn exec_with_options(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible> {
+        (&mut self).exec_with_options(name, options).await
+    }
+
+    async fn exec_auto(mut self) -> Result<Infallible> {
+        (&mut self).exec_auto().await
+    }
+
+    async fn check_auto(mut self) -> Result<std::process::Output> {
+        (&mut self).check_auto().await
+    }
+
+    async fn check_with_retry(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, policy: &RetryPolicy) -> Result<std::process::Output> {
+        (&mut self).check_with_retry(name, policy).await
+    }
+
+    #[cfg(unix)]
+    async fn check_pty(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<PtyOutput> {
+        (&mut self).check_pty(name).await
+    }
 }
 
 #[cfg(feature = "tokio")]
 impl<'a> AsyncCommandExt for &'a mut tokio::process::Command {
     async fn exec(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible> {
-        #[cfg(unix)] { Err(std::os::unix::process::CommandExt::exec(self.as_std_mut())).at_command(name) }
-        #[cfg(not(unix))] {
-            let name = name.into();
-            match self.check(name.clone()).await {
-                Ok(output) => std::process::exit(output.status.code().ok_or(Error::CommandExit { name, output })?),
-                Err(e) => Err(e),
+        #[cfg(feature = "futures")] if let Some(runner) = crate::process::current_runner() {
+            return runner.exec(self, name.into()).await
+        }
+        exec_command(self, name).await
+    }
+
+    async fn exec_with_options(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible> {
+        options.apply(self.as_std_mut());
+        self.exec(name).await
+    }
+
+    async fn exec_auto(self) -> Result<Infallible> {
+        let name = CommandDisplay::new(self.as_std());
+        self.exec(name).await
+    }
+
+    async fn check_auto(self) -> Result<std::process::Output> {
+        let name = CommandDisplay::new(self.as_std());
+        self.check(name).await
+    }
+
+    async fn check_with_retry(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, policy: &RetryPolicy) -> Result<std::process::Output> {
+        let start = Instant::now();
+        let mut attempts = Vec::default();
+        loop {
+            match (&mut *self).check(name.clone()).await {
+                Ok(output) => break Ok(output),
+                Err(e) => {
+                    let delay = policy.next_delay(start, attempts.len() + 1, &e);
+                    attempts.push(e);
+                    if let Some(delay) = delay {
+                        sleep(delay).await;
+                    } else {
+                        break Err(retries_error(name.into(), attempts))
+                    }
+                }
             }
         }
     }
+
+    #[cfg(unix)]
+    async fn check_pty(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<PtyOutput> {
+        let OpenptyResult { master, slave } = open_pty().at_command(name.clone())?;
+        let child = self.stdout(slave.try_clone().at_command(name.clone())?).stderr(slave).spawn();
+        // close our copies of the terminal so reading from it ends once the command exits, leaving the command usable with `check`
+        self.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = child.at_command(name.clone())?;
+        let reader = BackgroundReader::new(Some(PtyReader(File::from(master))));
+        let status = child.wait().await.at_command(name.clone())?;
+        let output = tokio::task::spawn_blocking(move || reader.join()).await.map_err(io::Error::from).flatten().at_command(name.clone())?;
+        PtyOutput { status, output }.check(name).map_err(detect_sudo_password(self.as_std()))
+    }
 }
 
 /// Extension methods for [`std::process::Command`]
 pub trait SyncCommandExt {
     /// Runs the command, then exits the current process, forwarding the command's exit status.
     ///
-    /// Uses the native `exec` on Unix, and an approximation using `check` on other platforms.
+    /// Uses the native `exec` on Unix. On other platforms, the command is run as a subprocess with inherited stdio, and the current process exits with its exit code once it exits. Ctrl+C is left for the subprocess to handle.
     fn exec(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible>;
+
+    /// Like `exec`, but first applies the given options to the command.
+    fn exec_with_options(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible>;
+
+    /// Like `exec`, but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    fn exec_auto(self) -> Result<Infallible>;
+
+    /// Like [`SyncCommandOutputExt::check`], but uses the command's program and arguments as its name. See [`CommandDisplay`].
+    fn check_auto(self) -> Result<std::process::Output>;
+
+    /// Like [`SyncCommandOutputExt::check`], but runs the command again if it fails with an error that the given policy considers retryable (see [`RetryPolicy::is_retryable`]).
+    ///
+    /// If the command is run more than once without succeeding, the errors from all attempts are returned as [`Error::CommandRetries`].
+    fn check_with_retry(self, name: impl Into<Cow<'static, str>> + Clone, policy: &RetryPolicy) -> Result<std::process::Output>;
+
+    #[cfg(unix)]
+    /// Like [`SyncCommandOutputExt::check`], but runs the command with its stdout and stderr attached to a pseudo-terminal, so it behaves as if run interactively, e.g. by using colours and progress bars.
+    ///
+    /// Since both streams go to the same terminal, they are captured together. If the command doesn't exit successfully, the combined output is returned as part of [`Error::CommandPtyExit`]. Stdin is used as configured. When called on a `&mut` command, its stdout and stderr are left configured as piped, as with the other `check` methods.
+    fn check_pty(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<PtyOutput>;
 }
 
 impl SyncCommandExt for std::process::Command {
     fn exec(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible> {
         (&mut self).exec(name)
     }
+
+    fn exec_with_options(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible> {
+        (&mut self).exec_with_options(name, options)
+    }
+
+    fn exec_auto(mut self) -> Result<Infallible> {
+        (&mut self).exec_auto()
+    }
+
+    fn check_auto(mut self) -> Result<std::process::Output> {
+        (&mut self).check_auto()
+    }
+
+    fn check_with_retry(mut self, name: impl Into<Cow<'static, str>> + Clone, policy: &RetryPolicy) -> Result<std::process::Output> {
+        (&mut self).check_with_retry(name, policy)
+    }
+
+    #[cfg(unix)]
+    fn check_pty(mut self, name: impl Into<Cow<'static, str>> + Clone) -> Result<PtyOutput> {
+        (&mut self).check_pty(name)
+    }
 }
 
 impl<'a> SyncCommandExt for &'a mut std::process::Command {
     fn exec(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Infallible> {
         #[cfg(unix)] { Err(std::os::unix::process::CommandExt::exec(self)).at_command(name) }
         #[cfg(not(unix))] {
-            let name = name.into();
-            match self.check(name.clone()) {
-                Ok(output) => std::process::exit(output.status.code().ok_or(Error::CommandExit { name, output })?),
-                Err(e) => Err(e),
+            // the child shares our console so it receives Ctrl+C as well, let it decide whether to exit
+            let _ = ctrlc::set_handler(|| {});
+            let status = self.status().at_command(name)?;
+            std::process::exit(status.code().unwrap_or(1))
+        }
+    }
+
+    fn exec_with_options(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, options: &ExecOptions) -> Result<Infallible> {
+        options.apply(self);
+        self.exec(name)
+    }
+
+    fn exec_auto(self) -> Result<Infallible> {
+        let name = CommandDisplay::new(self);
+        self.exec(name)
+    }
+
+    fn check_auto(self) -> Result<std::process::Output> {
+        let name = CommandDisplay::new(self);
+        self.check(name)
+    }
+
+    fn check_with_retry(self, name: impl Into<Cow<'static, str>> + Clone, policy: &RetryPolicy) -> Result<std::process::Output> {
+        let start = Instant::now();
+        let mut attempts = Vec::default();
+        loop {
+            match (&mut *self).check(name.clone()) {
+                Ok(output) => break Ok(output),
+                Err(e) => {
+                    let delay = policy.next_delay(start, attempts.len() + 1, &e);
+                    attempts.push(e);
+                    if let Some(delay) = delay {
+                        thread::sleep(delay);
+                    } else {
+                        break Err(retries_error(name.into(), attempts))
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn check_pty(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<PtyOutput> {
+        let OpenptyResult { master, slave } = open_pty().at_command(name.clone())?;
+        let child = self.stdout(slave.try_clone().at_command(name.clone())?).stderr(slave).spawn();
+        // close our copies of the terminal so reading from it ends once the command exits, leaving the command usable with `check`
+        self.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = child.at_command(name.clone())?;
+        let reader = BackgroundReader::new(Some(PtyReader(File::from(master))));
+        let status = child.wait().at_command(name.clone())?;
+        let output = reader.join().at_command(name.clone())?;
+        PtyOutput { status, output }.check(name).map_err(detect_sudo_password(self))
+    }
+}
+
+/// Options for `exec_with_options`, applied to the command right before it replaces the current process.
+#[derive(Debug, Default, Clone)]
+pub struct ExecOptions {
+    clear_env: bool,
+    keep_env: Vec<OsString>,
+    #[cfg(unix)] arg0: Option<OsString>,
+    current_dir: Option<PathBuf>,
+}
+
+impl ExecOptions {
+    /// Creates options which leave the command unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Doesn't pass the current process's environment variables to the command, except for those allowed using [`ExecOptions::keep_env`]. Environment variables set explicitly on the command are still passed.
+    pub fn clear_env(mut self) -> Self {
+        self.clear_env = true;
+        self
+    }
+
+    /// Passes the given environment variable from the current process even if [`ExecOptions::clear_env`] is used.
+    pub fn keep_env(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.keep_env.push(key.as_ref().to_owned());
+        self
+    }
+
+    #[cfg(unix)]
+    /// Sets the name the command sees as its `argv[0]`, instead of the program path.
+    pub fn arg0(mut self, arg0: impl AsRef<OsStr>) -> Self {
+        self.arg0 = Some(arg0.as_ref().to_owned());
+        self
+    }
+
+    /// Runs the command in the given working directory.
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    fn apply(&self, cmd: &mut std::process::Command) {
+        if self.clear_env {
+            let explicit = cmd.get_envs().map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned))).collect::<Vec<_>>();
+            cmd.env_clear();
+            for key in &self.keep_env {
+                if let Some(value) = env::var_os(key) {
+                    cmd.env(key, value);
+                }
             }
+            for (key, value) in explicit {
+                if let Some(value) = value {
+                    cmd.env(key, value);
+                } else {
+                    cmd.env_remove(key);
+                }
+            }
+        }
+        #[cfg(unix)] if let Some(ref arg0) = self.arg0 {
+            std::os::unix::process::CommandExt::arg0(cmd, arg0);
         }
+        if let Some(ref current_dir) = self.current_dir {
+            cmd.current_dir(current_dir);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// Replaces the current process with the given command, without going through the runner set using `process::with_runner`.
+pub(crate) async fn exec_command(cmd: &mut tokio::process::Command, name: impl Into<Cow<'static, str>>) -> Result<Infallible> {
+    #[cfg(unix)] { Err(std::os::unix::process::CommandExt::exec(cmd.as_std_mut())).at_command(name) }
+    #[cfg(not(unix))] {
+        // the child shares our console so it receives Ctrl+C as well, let it decide whether to exit
+        let _ = ctrlc::set_handler(|| {});
+        let status = cmd.status().await.at_command(name)?;
+        std::process::exit(status.code().unwrap_or(1))
     }
 }
 
+/// The error returned by `check_with_retry` once the given attempts have all failed.
+fn retries_error(name: Cow<'static, str>, mut attempts: Vec<Error>) -> Error {
+    if attempts.len() == 1 {
+        attempts.pop().expect("checked above")
+    } else {
+        Error::CommandRetries { name, attempts }
+    }
+}
+
+#[cfg(unix)]
+/// The output of a command run using `check_pty`.
+#[derive(Debug, Clone)]
+pub struct PtyOutput {
+    /// The exit status of the command.
+    pub status: std::process::ExitStatus,
+    /// Everything the command wrote to the terminal, including escape sequences such as colour codes. Line endings are `\r\n` since the terminal translates them.
+    pub output: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl PtyOutput {
+    /// The output with escape sequences such as colour codes removed and `\r\n` line endings replaced with `\n`.
+    pub fn stripped(&self) -> Vec<u8> {
+        strip_escapes(&self.output)
+    }
+
+    /// Like [`PtyOutput::stripped`], but decoded lossily.
+    pub fn stripped_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stripped()).into_owned()
+    }
+
+    fn check(self, name: impl Into<Cow<'static, str>>) -> Result<Self> {
+        if self.status.success() {
+            Ok(self)
+        } else {
+            Err(Error::CommandPtyExit { name: name.into(), output: self })
+        }
+    }
+}
+
+/// A line of output from a subprocess, as passed to the callback of [`AsyncCommandOutputExt::check_streaming`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputLine {
+    /// A line the subprocess wrote to its stdout.
+    Stdout(String),
+    /// A line the subprocess wrote to its stderr.
+    Stderr(String),
+}
+
 /// Adds a `check` method which errors if the command doesn't exit successfully.
 #[async_trait]
 pub trait AsyncCommandOutputExt {
@@ -311,6 +1046,44 @@ pub trait AsyncCommandOutputExt {
 
     /// Errors if the command doesn't exit successfully.
     async fn check(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Self::Ok>;
+
+    /// Like `check`, but kills the subprocess and returns [`Error::CommandTimeout`] if it doesn't exit within the given duration.
+    ///
+    /// On Unix, the subprocess is first sent `SIGTERM` and only killed using `SIGKILL` if it doesn't exit within 5 seconds after that.
+    ///
+    /// When called on a command, the subprocess is also killed if the returned future is dropped before it exits, e.g. because the task was cancelled. When called on a child process, this only happens if it was spawned with [`kill_on_drop`](tokio::process::Command::kill_on_drop) set.
+    async fn check_timeout(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, timeout: Duration) -> Result<Self::Ok>;
+
+    /// Like `check`, but writes the given bytes to the subprocess's stdin, then closes it.
+    ///
+    /// The input is written concurrently with reading the subprocess's output, so large inputs don't cause a deadlock. If the subprocess exits without reading all of its input, the remaining input is discarded.
+    async fn check_with_stdin(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, input: impl AsRef<[u8]> + Send) -> Result<Self::Ok>;
+
+    /// Like `check`, but returns the subprocess's stdout decoded as UTF-8.
+    async fn check_stdout_string(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<String>
+    where Self: AsyncCommandOutputExt<Ok = std::process::Output> + Send + Sized {
+        let output = self.check(name.clone()).await?;
+        stdout_string(name, output.stdout)
+    }
+
+    /// Like `check`, but returns the lines of the subprocess's stdout decoded as UTF-8.
+    async fn check_stdout_lines(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Vec<String>>
+    where Self: AsyncCommandOutputExt<Ok = std::process::Output> + Send + Sized {
+        Ok(self.check_stdout_string(name).await?.lines().map(String::from).collect())
+    }
+
+    #[cfg(feature = "serde_json")]
+    /// Like `check`, but deserializes the subprocess's stdout as JSON.
+    async fn check_json<T: DeserializeOwned>(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<T>
+    where Self: AsyncCommandOutputExt<Ok = std::process::Output> + Send + Sized {
+        let output = self.check(name.clone()).await?;
+        stdout_json(name, output.stdout)
+    }
+
+    /// Like `check`, but calls `on_line` with each line of the subprocess's stdout and stderr as soon as it's available, rather than returning the output at the end.
+    ///
+    /// Lines are decoded lossily and passed without their line terminator. If the subprocess doesn't exit successfully, the [`Error::CommandExit`] only includes the last 64 KiB each of stdout and stderr.
+    async fn check_streaming(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, on_line: impl FnMut(OutputLine) + Send) -> Result<std::process::ExitStatus>;
 }
 
 #[cfg(feature = "tokio")]
@@ -321,6 +1094,18 @@ impl AsyncCommandOutputExt for tokio::process::Command {
     async fn check(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Self::Ok> {
         (&mut self).check(name).await
     }
+
+    async fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, timeout: Duration) -> Result<Self::Ok> {
+        (&mut self).check_timeout(name, timeout).await
+    }
+
+    async fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, input: impl AsRef<[u8]> + Send) -> Result<Self::Ok> {
+        (&mut self).check_with_stdin(name, input).await
+    }
+
+    async fn check_streaming(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, on_line: impl FnMut(OutputLine) + Send) -> Result<std::process::ExitStatus> {
+        (&mut self).check_streaming(name, on_line).await
+    }
 }
 
 #[cfg(feature = "tokio")]
@@ -329,13 +1114,32 @@ impl<'a> AsyncCommandOutputExt for &'a mut tokio::process::Command {
     type Ok = std::process::Output;
 
     async fn check(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static) -> Result<Self::Ok> {
+        #[cfg(feature = "futures")] if let Some(runner) = crate::process::current_runner() {
+            let detect_sudo_password = detect_sudo_password(self.as_std());
+            return runner.check(self, name.into()).await.map_err(detect_sudo_password)
+        }
         let output = self.output().await.at_command(name.clone())?;
         if output.status.success() {
             Ok(output)
         } else {
-            Err(Error::CommandExit { name: name.into(), output })
+            Err(Error::CommandExit { name: name.into(), output }).map_err(detect_sudo_password(self.as_std()))
         }
     }
+
+    async fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, timeout: Duration) -> Result<Self::Ok> {
+        let detect_sudo_password = detect_sudo_password(self.as_std());
+        self.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true).spawn().at_command(name.clone())?.check_timeout(name, timeout).await.map_err(detect_sudo_password)
+    }
+
+    async fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, input: impl AsRef<[u8]> + Send) -> Result<Self::Ok> {
+        let detect_sudo_password = detect_sudo_password(self.as_std());
+        self.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().at_command(name.clone())?.check_with_stdin(name, input).await.map_err(detect_sudo_password)
+    }
+
+    async fn check_streaming(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, on_line: impl FnMut(OutputLine) + Send) -> Result<std::process::ExitStatus> {
+        let detect_sudo_password = detect_sudo_password(self.as_std());
+        self.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().at_command(name.clone())?.check_streaming(name, on_line).await.map_err(detect_sudo_password)
+    }
 }
 
 #[cfg(feature = "tokio")]
@@ -351,6 +1155,45 @@ impl AsyncCommandOutputExt for tokio::process::Child {
             Err(Error::CommandExit { name: name.into(), output })
         }
     }
+
+    async fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, timeout: Duration) -> Result<Self::Ok> {
+        let mut stdout = Vec::default();
+        let mut stderr = Vec::default();
+        let mut stdout_pipe = self.stdout.take();
+        let mut stderr_pipe = self.stderr.take();
+        let result = tokio::time::timeout(timeout, async {
+            tokio::try_join!(
+                self.wait(),
+                async {
+                    if let Some(stdout_pipe) = &mut stdout_pipe { stdout_pipe.read_to_end(&mut stdout).await?; }
+                    io::Result::Ok(())
+                },
+                async {
+                    if let Some(stderr_pipe) = &mut stderr_pipe { stderr_pipe.read_to_end(&mut stderr).await?; }
+                    io::Result::Ok(())
+                },
+            )
+        }).await;
+        match result {
+            Ok(Ok((status, (), ()))) => std::process::Output { status, stdout, stderr }.check(name),
+            Ok(Err(e)) => Err(e).at_command(name),
+            Err(_) => {
+                let status = terminate(&mut self).await.at_command(name.clone())?;
+                Err(Error::CommandTimeout { name: name.into(), partial_output: std::process::Output { status, stdout, stderr } })
+            }
+        }
+    }
+
+    async fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, input: impl AsRef<[u8]> + Send) -> Result<Self::Ok> {
+        let stdin = self.stdin.take().ok_or_else(stdin_not_piped).at_command(name.clone())?;
+        let (write_result, output) = tokio::join!(write_stdin(stdin, input), self.wait_with_output());
+        write_result.at_command(name.clone())?;
+        output.at_command(name.clone())?.check(name)
+    }
+
+    async fn check_streaming(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, on_line: impl FnMut(OutputLine) + Send) -> Result<std::process::ExitStatus> {
+        (&mut self).check_streaming(name, on_line).await
+    }
 }
 
 #[cfg(feature = "tokio")]
@@ -366,6 +1209,59 @@ impl<'a> AsyncCommandOutputExt for &'a mut tokio::process::Child {
             Err(Error::CommandExitStatus { name: name.into(), status })
         }
     }
+
+    async fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, timeout: Duration) -> Result<Self::Ok> {
+        match tokio::time::timeout(timeout, self.wait()).await {
+            Ok(status) => status.at_command(name.clone())?.check(name),
+            Err(_) => {
+                let status = terminate(self).await.at_command(name.clone())?;
+                Err(Error::CommandTimeout { name: name.into(), partial_output: std::process::Output { status, stdout: Vec::default(), stderr: Vec::default() } })
+            }
+        }
+    }
+
+    async fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, input: impl AsRef<[u8]> + Send) -> Result<Self::Ok> {
+        let stdin = self.stdin.take().ok_or_else(stdin_not_piped).at_command(name.clone())?;
+        let (write_result, status) = tokio::join!(write_stdin(stdin, input), self.wait());
+        write_result.at_command(name.clone())?;
+        status.at_command(name.clone())?.check(name)
+    }
+
+    async fn check_streaming(self, name: impl Into<Cow<'static, str>> + Clone + Send + 'static, mut on_line: impl FnMut(OutputLine) + Send) -> Result<std::process::ExitStatus> {
+        let mut stdout = self.stdout.take().map(BufReader::new);
+        let mut stderr = self.stderr.take().map(BufReader::new);
+        let mut stdout_line = Vec::default();
+        let mut stderr_line = Vec::default();
+        let mut stdout_tail = Vec::default();
+        let mut stderr_tail = Vec::default();
+        loop {
+            tokio::select! {
+                n = async { stdout.as_mut().expect("checked by select precondition").read_until(b'\n', &mut stdout_line).await }, if stdout.is_some() => {
+                    if n.at_command(name.clone())? == 0 { stdout = None }
+                    if stdout.is_none() || stdout_line.ends_with(b"\n") {
+                        push_tail(&mut stdout_tail, &stdout_line);
+                        if !stdout_line.is_empty() { on_line(OutputLine::Stdout(decode_line(&stdout_line))) }
+                        stdout_line.clear();
+                    }
+                }
+                n = async { stderr.as_mut().expect("checked by select precondition").read_until(b'\n', &mut stderr_line).await }, if stderr.is_some() => {
+                    if n.at_command(name.clone())? == 0 { stderr = None }
+                    if stderr.is_none() || stderr_line.ends_with(b"\n") {
+                        push_tail(&mut stderr_tail, &stderr_line);
+                        if !stderr_line.is_empty() { on_line(OutputLine::Stderr(decode_line(&stderr_line))) }
+                        stderr_line.clear();
+                    }
+                }
+                else => break,
+            }
+        }
+        let status = self.wait().await.at_command(name.clone())?;
+        if status.success() {
+            Ok(status)
+        } else {
+            Err(Error::CommandExit { name: name.into(), output: std::process::Output { status, stdout: stdout_tail, stderr: stderr_tail } })
+        }
+    }
 }
 
 /// Adds a `check` method which errors if the command doesn't exit successfully.
@@ -375,6 +1271,37 @@ pub trait SyncCommandOutputExt {
 
     /// Errors if the command doesn't exit successfully.
     fn check(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<Self::Ok>;
+
+    /// Like `check`, but kills the subprocess and returns [`Error::CommandTimeout`] if it doesn't exit within the given duration.
+    ///
+    /// On Unix, the subprocess is first sent `SIGTERM` and only killed using `SIGKILL` if it doesn't exit within 5 seconds after that. If the subprocess has already exited, this is equivalent to `check`. If the subprocess exits but its captured output pipes are kept open past the timeout, e.g. by a background process it started, [`Error::CommandTimeout`] is returned as well.
+    fn check_timeout(self, name: impl Into<Cow<'static, str>> + Clone, timeout: Duration) -> Result<Self::Ok>;
+
+    /// Like `check`, but writes the given bytes to the subprocess's stdin, then closes it.
+    ///
+    /// The input is written concurrently with reading the subprocess's output, so large inputs don't cause a deadlock. If the subprocess exits without reading all of its input, the remaining input is discarded.
+    fn check_with_stdin(self, name: impl Into<Cow<'static, str>> + Clone, input: impl AsRef<[u8]>) -> Result<Self::Ok>;
+
+    /// Like `check`, but returns the subprocess's stdout decoded as UTF-8.
+    fn check_stdout_string(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<String>
+    where Self: SyncCommandOutputExt<Ok = std::process::Output> + Sized {
+        let output = self.check(name.clone())?;
+        stdout_string(name, output.stdout)
+    }
+
+    /// Like `check`, but returns the lines of the subprocess's stdout decoded as UTF-8.
+    fn check_stdout_lines(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<Vec<String>>
+    where Self: SyncCommandOutputExt<Ok = std::process::Output> + Sized {
+        Ok(self.check_stdout_string(name)?.lines().map(String::from).collect())
+    }
+
+    #[cfg(feature = "serde_json")]
+    /// Like `check`, but deserializes the subprocess's stdout as JSON.
+    fn check_json<T: DeserializeOwned>(self, name: impl Into<Cow<'static, str>> + Clone) -> Result<T>
+    where Self: SyncCommandOutputExt<Ok = std::process::Output> + Sized {
+        let output = self.check(name.clone())?;
+        stdout_json(name, output.stdout)
+    }
 }
 
 impl SyncCommandOutputExt for std::process::Command {
@@ -383,6 +1310,14 @@ impl SyncCommandOutputExt for std::process::Command {
     fn check(mut self, name: impl Into<Cow<'static, str>> + Clone) -> Result<Self::Ok> {
         (&mut self).check(name)
     }
+
+    fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone, timeout: Duration) -> Result<Self::Ok> {
+        (&mut self).check_timeout(name, timeout)
+    }
+
+    fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone, input: impl AsRef<[u8]>) -> Result<Self::Ok> {
+        (&mut self).check_with_stdin(name, input)
+    }
 }
 
 impl<'a> SyncCommandOutputExt for &'a mut std::process::Command {
@@ -393,9 +1328,19 @@ impl<'a> SyncCommandOutputExt for &'a mut std::process::Command {
         if output.status.success() {
             Ok(output)
         } else {
-            Err(Error::CommandExit { name: name.into(), output })
+            Err(Error::CommandExit { name: name.into(), output }).map_err(detect_sudo_password(self))
         }
     }
+
+    fn check_timeout(self, name: impl Into<Cow<'static, str>> + Clone, timeout: Duration) -> Result<Self::Ok> {
+        let detect_sudo_password = detect_sudo_password(self);
+        self.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().at_command(name.clone())?.check_timeout(name, timeout).map_err(detect_sudo_password)
+    }
+
+    fn check_with_stdin(self, name: impl Into<Cow<'static, str>> + Clone, input: impl AsRef<[u8]>) -> Result<Self::Ok> {
+        let detect_sudo_password = detect_sudo_password(self);
+        self.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().at_command(name.clone())?.check_with_stdin(name, input).map_err(detect_sudo_password)
+    }
 }
 
 impl SyncCommandOutputExt for std::process::Child {
@@ -409,6 +1354,45 @@ impl SyncCommandOutputExt for std::process::Child {
             Err(Error::CommandExit { name: name.into(), output })
         }
     }
+
+    fn check_timeout(mut self, name: impl Into<Cow<'static, str>> + Clone, timeout: Duration) -> Result<Self::Ok> {
+        let deadline = Instant::now() + timeout;
+        let stdout = BackgroundReader::new(self.stdout.take());
+        let stderr = BackgroundReader::new(self.stderr.take());
+        let mut status = None;
+        loop {
+            if status.is_none() {
+                status = self.try_wait().at_command(name.clone())?;
+            }
+            // the pipes can be held open by the subprocess's own children after it exits, so they're also subject to the timeout
+            if let Some(status) = status && stdout.is_finished() && stderr.is_finished() {
+                let stdout = stdout.join().at_command(name.clone())?;
+                let stderr = stderr.join().at_command(name.clone())?;
+                break std::process::Output { status, stdout, stderr }.check(name)
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                let status = if let Some(status) = status { status } else { terminate_sync(&mut self).at_command(name.clone())? };
+                break Err(Error::CommandTimeout { name: name.into(), partial_output: std::process::Output { status, stdout: stdout.partial(), stderr: stderr.partial() } })
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    fn check_with_stdin(mut self, name: impl Into<Cow<'static, str>> + Clone, input: impl AsRef<[u8]>) -> Result<Self::Ok> {
+        let mut stdin = self.stdin.take().ok_or_else(stdin_not_piped).at_command(name.clone())?;
+        let input = input.as_ref();
+        let (write_result, output) = thread::scope(|scope| {
+            let writer = scope.spawn(move || match stdin.write_all(input) {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()), // the subprocess closed its stdin early, report its exit status instead
+                result => result,
+            });
+            let output = self.wait_with_output();
+            (writer.join().unwrap_or_else(|e| std::panic::resume_unwind(e)), output)
+        });
+        write_result.at_command(name.clone())?;
+        output.at_command(name.clone())?.check(name)
+    }
 }
 
 impl SyncCommandOutputExt for std::process::Output {
@@ -421,6 +1405,14 @@ impl SyncCommandOutputExt for std::process::Output {
             Err(Error::CommandExit { name: name.into(), output: self })
         }
     }
+
+    fn check_timeout(self, name: impl Into<Cow<'static, str>> + Clone, _: Duration) -> Result<Self::Ok> {
+        self.check(name)
+    }
+
+    fn check_with_stdin(self, name: impl Into<Cow<'static, str>> + Clone, _: impl AsRef<[u8]>) -> Result<Self::Ok> {
+        Err(already_exited()).at_command(name)
+    }
 }
 
 impl SyncCommandOutputExt for std::process::ExitStatus {
@@ -433,6 +1425,209 @@ impl SyncCommandOutputExt for std::process::ExitStatus {
             Err(Error::CommandExitStatus { name: name.into(), status: self })
         }
     }
+
+    fn check_timeout(self, name: impl Into<Cow<'static, str>> + Clone, _: Duration) -> Result<Self::Ok> {
+        self.check(name)
+    }
+
+    fn check_with_stdin(self, name: impl Into<Cow<'static, str>> + Clone, _: impl AsRef<[u8]>) -> Result<Self::Ok> {
+        Err(already_exited()).at_command(name)
+    }
+}
+
+/// How long `check_timeout` waits for a subprocess to exit after sending `SIGTERM` before sending `SIGKILL`.
+pub(crate) const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+/// How often to check whether a subprocess has exited when no notification mechanism is available.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Terminates the given subprocess, first gracefully if supported by the platform, then forcibly.
+#[cfg(feature = "tokio")]
+async fn terminate(child: &mut tokio::process::Child) -> io::Result<std::process::ExitStatus> {
+    #[cfg(unix)] if let Some(pid) = child.id()
+        && kill(Pid::from_raw(pid as i32), Signal::SIGTERM).is_ok()
+        && let Ok(status) = tokio::time::timeout(KILL_GRACE_PERIOD, child.wait()).await
+    {
+        return status
+    }
+    child.kill().await?;
+    child.wait().await
+}
+
+/// Terminates the given subprocess, first gracefully if supported by the platform, then forcibly.
+fn terminate_sync(child: &mut std::process::Child) -> io::Result<std::process::ExitStatus> {
+    #[cfg(unix)] if kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM).is_ok() {
+        let deadline = Instant::now() + KILL_GRACE_PERIOD;
+        while Instant::now() < deadline {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status)
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    child.kill()?;
+    child.wait()
+}
+
+#[cfg(feature = "tokio")]
+/// Writes the given input to a subprocess's stdin, then closes it.
+async fn write_stdin(mut stdin: tokio::process::ChildStdin, input: impl AsRef<[u8]>) -> io::Result<()> {
+    match stdin.write_all(input.as_ref()).await {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()), // the subprocess closed its stdin early, report its exit status instead
+        result => result,
+    }
+}
+
+/// The maximum number of bytes of a subprocess's output included in errors.
+const EXCERPT_LEN: usize = 1024;
+
+/// Lossily decodes the beginning of a subprocess's output for inclusion in an error.
+fn excerpt(output: &[u8]) -> String {
+    if output.len() > EXCERPT_LEN {
+        format!("{}…", String::from_utf8_lossy(&output[..EXCERPT_LEN]))
+    } else {
+        String::from_utf8_lossy(output).into_owned()
+    }
+}
+
+fn stdout_string(name: impl Into<Cow<'static, str>>, stdout: Vec<u8>) -> Result<String> {
+    String::from_utf8(stdout).map_err(|e| Error::CommandUtf8 {
+        name: name.into(),
+        inner: e.utf8_error(),
+        excerpt: excerpt(e.as_bytes()),
+    })
+}
+
+#[cfg(feature = "serde_json")]
+fn stdout_json<T: DeserializeOwned>(name: impl Into<Cow<'static, str>>, stdout: Vec<u8>) -> Result<T> {
+    serde_json_path_to_error::from_slice(&stdout).map_err(|inner| Error::CommandJson {
+        name: name.into(),
+        excerpt: excerpt(&stdout),
+        inner,
+    })
+}
+
+/// The maximum number of bytes each of stdout and stderr kept by `check_streaming` for error reporting.
+#[cfg(feature = "tokio")]
+const OUTPUT_TAIL_LEN: usize = 64 * 1024;
+
+/// Appends a line to the output kept by `check_streaming`, discarding the oldest output if necessary.
+#[cfg(feature = "tokio")]
+fn push_tail(tail: &mut Vec<u8>, line: &[u8]) {
+    tail.extend_from_slice(line);
+    if tail.len() > OUTPUT_TAIL_LEN {
+        tail.drain(..tail.len() - OUTPUT_TAIL_LEN);
+    }
+}
+
+/// Lossily decodes a line of output, stripping the line terminator.
+#[cfg(feature = "tokio")]
+fn decode_line(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+fn stdin_not_piped() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "the subprocess's stdin is not piped")
+}
+
+fn already_exited() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the subprocess has already exited")
+}
+
+/// Reads a pipe to the end on a separate thread, making the data read so far available at any time.
+struct BackgroundReader {
+    buf: Arc<Mutex<Vec<u8>>>,
+    thread: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl BackgroundReader {
+    fn new(pipe: Option<impl Read + Send + 'static>) -> Self {
+        let buf = Arc::<Mutex<Vec<u8>>>::default();
+        let thread = pipe.map(|mut pipe| {
+            let buf = Arc::clone(&buf);
+            thread::spawn(move || {
+                let mut chunk = [0; 8192];
+                loop {
+                    match pipe.read(&mut chunk) {
+                        Ok(0) => break Ok(()),
+                        Ok(n) => buf.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(&chunk[..n]),
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => break Err(e),
+                    }
+                }
+            })
+        });
+        Self { buf, thread }
+    }
+
+    /// Returns the data read so far without waiting for the pipe to be closed.
+    fn partial(&self) -> Vec<u8> {
+        self.buf.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Returns whether the pipe has been closed, so that [`BackgroundReader::join`] won't block.
+    fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(thread::JoinHandle::is_finished)
+    }
+
+    /// Waits for the pipe to be closed and returns all data read from it.
+    fn join(self) -> io::Result<Vec<u8>> {
+        if let Some(thread) = self.thread {
+            thread.join().unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+        }
+        Ok(mem::take(&mut self.buf.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+#[cfg(unix)]
+/// Opens a pseudo-terminal with the conventional default size of 80×24 characters.
+fn open_pty() -> io::Result<OpenptyResult> {
+    Ok(openpty(&Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 }, None)?)
+}
+
+#[cfg(unix)]
+/// The controller side of a pseudo-terminal. Reading from it fails with `EIO` once all processes have closed the other side, which is treated as the end of the output.
+struct PtyReader(File);
+
+#[cfg(unix)]
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(Errno::EIO as i32) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+#[cfg(unix)]
+/// Removes terminal escape sequences and replaces `\r\n` with `\n`.
+fn strip_escapes(output: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(output.len());
+    let mut bytes = output.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            0x1b => match bytes.next() {
+                // control sequence, e.g. colours and cursor movement
+                Some(b'[') => for byte in bytes.by_ref() {
+                    if (0x40..=0x7e).contains(&byte) { break }
+                },
+                // operating system command, e.g. window titles and hyperlinks
+                Some(b']') => while let Some(byte) = bytes.next() {
+                    if byte == 0x07 { break }
+                    if byte == 0x1b {
+                        bytes.next_if_eq(&b'\\');
+                        break
+                    }
+                },
+                // other sequences consist of a single byte after the escape
+                _ => {}
+            },
+            b'\r' if bytes.peek() == Some(&b'\n') => {}
+            _ => stripped.push(byte),
+        }
+    }
+    stripped
 }
 
 #[cfg(all(feature = "chrono", feature = "reqwest"))]
@@ -550,6 +1745,110 @@ impl ReqwestResponseExt for reqwest::Response {
     }
 }
 
+/// Determines the status code the process exits with when this error is returned from `main`. See [`MainOutput`](crate::MainOutput).
+///
+/// Implementations should follow the conventions in [`sysexits`](crate::sysexits) where applicable. The implementation for [`Error`] uses the variant and [`IsNetworkError`]; implementations for other error enums can be derived.
+pub trait ToExitCode {
+    /// The status code the process should exit with.
+    fn to_exit_code(&self) -> i32;
+}
+
+impl ToExitCode for Error {
+    /// * Network errors: [`TEMPFAIL`](crate::sysexits::TEMPFAIL)
+    /// * I/O and JSON errors annotated with an environment variable or configuration key: [`CONFIG`](crate::sysexits::CONFIG)
+    /// * Commands which couldn't be started because the program wasn't found: [`UNAVAILABLE`](crate::sysexits::UNAVAILABLE)
+    /// * Other I/O errors: as for [`io::Error`]
+    /// * JSON errors, including invalid JSON or UTF-8 output from commands: [`DATAERR`](crate::sysexits::DATAERR)
+    /// * Commands which failed, timed out, or required a password for `sudo`: [`SOFTWARE`](crate::sysexits::SOFTWARE)
+    /// * Errors annotated using [`ResultContextExt`] and [`Error::Shared`] use the exit code of the underlying error.
+    /// * Anything else: 1
+    fn to_exit_code(&self) -> i32 {
+        if self.is_network_error() { return crate::sysexits::TEMPFAIL }
+        match self {
+            Self::Context { inner, .. } => inner.to_exit_code(),
+            Self::Shared(e) => e.to_exit_code(),
+            Self::Io { context: IoErrorContext::EnvVar(_) | IoErrorContext::ConfigKey { .. }, .. } => crate::sysexits::CONFIG,
+            Self::Io { inner, context: IoErrorContext::Command(_) } if inner.kind() == io::ErrorKind::NotFound => crate::sysexits::UNAVAILABLE,
+            Self::Io { inner, .. } => inner.to_exit_code(),
+            #[cfg(feature = "serde_json")] Self::Json { context: IoErrorContext::EnvVar(_) | IoErrorContext::ConfigKey { .. }, .. } => crate::sysexits::CONFIG,
+            #[cfg(feature = "serde_json")] Self::JsonPathToError { context: IoErrorContext::EnvVar(_) | IoErrorContext::ConfigKey { .. }, .. } => crate::sysexits::CONFIG,
+            #[cfg(feature = "serde_json")] Self::CommandJson { .. } | Self::Json { .. } | Self::JsonPathToError { .. } => crate::sysexits::DATAERR,
+            #[cfg(all(feature = "reqwest", feature = "serde_json"))] Self::ResponseJson { .. } | Self::ResponseJsonPathToError { .. } => crate::sysexits::DATAERR,
+            Self::CommandUtf8 { .. } => crate::sysexits::DATAERR,
+            #[cfg(unix)] Self::CommandPtyExit { .. } => crate::sysexits::SOFTWARE,
+            | Self::CommandExit { .. }
+            | Self::CommandExitStatus { .. }
+            | Self::CommandRetries { .. }
+            | Self::CommandTimeout { .. }
+            | Self::CommandsFailed { .. }
+            | Self::SudoPasswordRequired { .. }
+                => crate::sysexits::SOFTWARE,
+            #[allow(unreachable_patterns)] // depending on enabled features
+            _ => 1,
+        }
+    }
+}
+
+impl ToExitCode for SharedError {
+    fn to_exit_code(&self) -> i32 {
+        (**self).to_exit_code()
+    }
+}
+
+impl ToExitCode for io::Error {
+    /// * Network errors: [`TEMPFAIL`](crate::sysexits::TEMPFAIL)
+    /// * Errors of kind [`io::ErrorKind::NotFound`]: [`NOINPUT`](crate::sysexits::NOINPUT)
+    /// * Errors of kind [`io::ErrorKind::PermissionDenied`]: [`NOPERM`](crate::sysexits::NOPERM)
+    /// * Anything else: [`IOERR`](crate::sysexits::IOERR)
+    fn to_exit_code(&self) -> i32 {
+        if self.is_network_error() { return crate::sysexits::TEMPFAIL }
+        match self.kind() {
+            io::ErrorKind::NotFound => crate::sysexits::NOINPUT,
+            io::ErrorKind::PermissionDenied => crate::sysexits::NOPERM,
+            _ => crate::sysexits::IOERR,
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl ToExitCode for serde_json::Error {
+    fn to_exit_code(&self) -> i32 {
+        if let Some(kind) = self.io_error_kind() {
+            io::Error::from(kind).to_exit_code()
+        } else {
+            crate::sysexits::DATAERR
+        }
+    }
+}
+
+impl ToExitCode for Infallible {
+    fn to_exit_code(&self) -> i32 {
+        match *self {}
+    }
+}
+
+impl ToExitCode for String {
+    fn to_exit_code(&self) -> i32 { 1 }
+}
+
+impl ToExitCode for &str {
+    fn to_exit_code(&self) -> i32 { 1 }
+}
+
+impl ToExitCode for Box<dyn std::error::Error> {
+    fn to_exit_code(&self) -> i32 { 1 }
+}
+
+impl ToExitCode for Box<dyn std::error::Error + Send + Sync> {
+    fn to_exit_code(&self) -> i32 { 1 }
+}
+
+impl<T: ToExitCode + ?Sized> ToExitCode for Arc<T> {
+    fn to_exit_code(&self) -> i32 {
+        (**self).to_exit_code()
+    }
+}
+
 /// A heuristic for whether an error is a network error outside of our control that might be fixed by retrying the operation.
 pub trait IsNetworkError {
     /// A heuristic for whether an error is a network error outside of our control that might be fixed by retrying the operation.
@@ -559,6 +1858,8 @@ pub trait IsNetworkError {
 impl IsNetworkError for Error {
     fn is_network_error(&self) -> bool {
         match self {
+            Self::Context { inner, .. } => inner.is_network_error(),
+            Self::Shared(e) => e.is_network_error(),
             Self::Io { inner, .. } => inner.is_network_error(),
             #[cfg(all(feature = "reqwest", feature = "serde_json"))] Self::Reqwest(e) => e.is_network_error(),
             #[cfg(feature = "reqwest")] Self::ResponseStatus { inner, .. } => inner.is_network_error(),
@@ -567,6 +1868,12 @@ impl IsNetworkError for Error {
     }
 }
 
+impl IsNetworkError for SharedError {
+    fn is_network_error(&self) -> bool {
+        (**self).is_network_error()
+    }
+}
+
 impl IsNetworkError for io::Error {
     fn is_network_error(&self) -> bool {
         matches!(self.kind(),
@@ -797,3 +2104,30 @@ impl<T> SendResultExt for Result<(), tokio::sync::mpsc::error::SendError<T>> {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::strip_escapes;
+
+    #[test]
+    fn strip_csi() {
+        assert_eq!(strip_escapes(b"\x1b[1;31merror\x1b[0m: failed\x1b[K"), b"error: failed");
+    }
+
+    #[test]
+    fn strip_osc() {
+        assert_eq!(strip_escapes(b"\x1b]0;title\x07text"), b"text");
+        assert_eq!(strip_escapes(b"\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"), b"link");
+    }
+
+    #[test]
+    fn strip_single_byte_escapes_and_crlf() {
+        assert_eq!(strip_escapes(b"a\x1b=b\r\nc\rd"), b"ab\nc\rd");
+    }
+
+    #[test]
+    fn unterminated() {
+        assert_eq!(strip_escapes(b"text\x1b[31"), b"text");
+        assert_eq!(strip_escapes(b"text\x1b"), b"text");
+    }
+}